@@ -0,0 +1,24 @@
+//! Records the version of bat locked for this build, for `--capabilities`.
+
+use std::fs;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let Ok(lock) = fs::read_to_string("Cargo.lock") else {
+        return;
+    };
+    // Packages are `[[package]]` tables with the name line right before the version one
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line == r#"name = "bat""# {
+            if let Some(version) = lines
+                .next()
+                .and_then(|line| line.strip_prefix(r#"version = ""#))
+                .and_then(|version| version.strip_suffix('"'))
+            {
+                println!("cargo:rustc-env=TOURUST_BAT_VERSION={version}");
+            }
+            return;
+        }
+    }
+}
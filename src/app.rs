@@ -1,423 +1,2732 @@
 use std::{
-    env, fs, io,
+    cell::RefCell,
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet},
+    env, fmt, fs,
+    io::{self, Stderr},
     path::{Path, PathBuf},
-    thread::sleep,
-    time::Duration,
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
+use arboard::Clipboard;
+use clap::ValueEnum;
 use futures::future::BoxFuture;
-use fuzzy_matcher::clangd::fuzzy_match;
-use log::debug;
+use fuzzy_matcher::{FuzzyMatcher, clangd::ClangdMatcher, skim::SkimMatcherV2};
+use log::{debug, error};
 use priority_queue::PriorityQueue;
 use ratatui::{
-    Terminal,
+    Terminal, TerminalOptions, Viewport,
     crossterm::{
         event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
         execute,
         terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
     },
-    prelude::CrosstermBackend,
+    layout::Position,
+    prelude::{Backend, CrosstermBackend},
     widgets::ListState,
 };
-use rust_search::SearchBuilder;
-use syn::{Item, spanned::Spanned};
-
-use crate::{error::Result, tui};
-
-#[derive(Hash, Default, Eq, PartialEq, Clone, Debug)]
-pub struct Ref {
-    pub line: usize,
-    pub column: usize,
-    pub file: PathBuf,
-    pub sig: String,
-}
-
-impl From<(Item, PathBuf)> for Ref {
-    fn from(value: (Item, PathBuf)) -> Self {
-        let sig = value.0.display();
-        match value.0 {
-            Item::Fn(item) => Self {
-                line: item.sig.span().start().line,
-                column: item.sig.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Mod(item) => Self {
-                line: item.ident.span().start().line,
-                column: item.ident.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Enum(item) => Self {
-                line: item.ident.span().start().line,
-                column: item.ident.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Trait(item) => Self {
-                line: item.ident.span().start().line,
-                column: item.ident.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Struct(item) => Self {
-                line: item.ident.span().start().line,
-                column: item.ident.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Use(item) => Self {
-                line: item.span().start().line,
-                column: item.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Type(item) => Self {
-                line: item.span().start().line,
-                column: item.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Impl(item) => Self {
-                line: item.self_ty.span().start().line,
-                column: item.self_ty.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Const(item) => Self {
-                line: item.span().start().line,
-                column: item.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Macro(item) => Self {
-                line: item.ident.span().start().line,
-                column: item.ident.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Static(item) => Self {
-                line: item.span().start().line,
-                column: item.span().start().column,
-                file: value.1,
-                sig,
-            },
-            Item::Union(item) => Self {
-                line: item.ident.span().start().line,
-                column: item.ident.span().start().column,
-                file: value.1,
-                sig,
-            },
-            _ => unimplemented!(),
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tourust::{
+    IndexOptions, Indexer, Progress, Ref, RefKind,
+    error::{Error, Result},
+};
+
+use crate::{
+    history::{self, History},
+    open,
+    query::Query,
+    theme::Theme,
+    tui,
+};
+
+/// Called with the chosen refs once the user confirms, either every marked ref in the order
+/// they were marked or just the highlighted one.
+pub trait SelectCallback {
+    fn call(&self, selection: Vec<Ref>) -> BoxFuture<'static, Result<()>>;
+}
+
+impl<T, F> SelectCallback for T
+where
+    T: Fn(Vec<Ref>) -> F,
+    F: Future<Output = Result<()>> + 'static + Send,
+{
+    fn call(&self, selection: Vec<Ref>) -> BoxFuture<'static, Result<()>> {
+        Box::pin(self(selection))
+    }
+}
+
+/// Delete the word before the cursor, along with any delimiters between it and the cursor.
+/// Underscores delimit words like whitespace does, so `parse_file_header` becomes `parse_file_`.
+fn delete_word(input: &mut String, cursor: &mut usize) {
+    let is_delimiter = |ch: char| ch.is_whitespace() || ch == '_';
+    let trimmed = input[..*cursor].trim_end_matches(is_delimiter);
+    let start = trimmed.trim_end_matches(|ch| !is_delimiter(ch)).len();
+    input.replace_range(start..*cursor, "");
+    *cursor = start;
+}
+
+/// Added to the score of refs by kind, so types or functions can rank first. Imports are
+/// rarely what one is looking for.
+pub fn default_kind_weights() -> HashMap<RefKind, i64> {
+    HashMap::from([
+        (RefKind::Fn, 5),
+        (RefKind::Method, 5),
+        (RefKind::Struct, 5),
+        (RefKind::Use, -10),
+    ])
+}
+
+/// What confirming the selection with Enter or Alt-Enter does, set per key in the config's
+/// `[keys]` table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Hand the selection to the editor backend, if there is one
+    #[default]
+    Jump,
+    /// Print where the selected symbols are once the picker closes
+    Print,
+    /// Copy where the selected symbols are to the clipboard
+    Copy,
+    /// Fill nvim's quickfix list with the results, like Ctrl-q
+    Quickfix,
+}
+
+impl Action {
+    /// Whether Enter closes the picker after this action. Copying keeps it open, as on X11 the
+    /// clipboard is emptied once the app exits, and so does filling the quickfix list, to show
+    /// whether that worked.
+    pub fn closes(&self) -> bool {
+        matches!(self, Action::Jump | Action::Print)
+    }
+}
+
+/// Where `r` is, like in compiler messages, e.g. `src/app.rs:12:5: crate::app::App::run`. The
+/// file is relative to `root` and the column counts from 1.
+pub fn location_line(r: &Ref, root: &Path) -> String {
+    let file = r.file.strip_prefix(root).unwrap_or(&r.file);
+    format!(
+        "{}:{}:{}: {}",
+        file.display(),
+        r.line,
+        r.column + 1,
+        r.path()
+    )
+}
+
+/// A markdown link to `r`, named after its path, e.g.
+/// `[crate::index::parse](https://github.com/owner/repo/blob/main/src/index.rs#L12)`. `{file}` and
+/// `{line}` in `template` are replaced by the ref's file relative to `root` and its line. Without
+/// a template this is just `file:line`.
+pub fn markdown_link(r: &Ref, root: &Path, template: Option<&str>) -> String {
+    let file = r.file.strip_prefix(root).unwrap_or(&r.file);
+    // URLs separate components with slashes whatever the platform
+    let file = file
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    match template {
+        Some(template) => {
+            let url = template
+                .replace("{file}", &file)
+                .replace("{line}", &r.line.to_string());
+            format!("[{}]({url})", r.path())
         }
+        None => format!("{file}:{}", r.line),
     }
 }
 
-pub trait IsRelevant {
-    fn is_relevant(&self) -> bool;
+/// How long to wait for input while indexing before checking for new refs.
+const INDEXING_POLL: Duration = Duration::from_millis(25);
+/// How long to wait for input once there is nothing else to do.
+const IDLE_POLL: Duration = Duration::from_secs(1);
+
+/// How long a jump may take before the picker stops waiting on the editor, by default.
+pub const DEFAULT_CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A jump the select callback is still busy with, running in the background so the picker
+/// keeps drawing and can cancel it.
+struct Pending {
+    task: JoinHandle<Result<()>>,
+    selection: Vec<Ref>,
+    /// Whether the picker closes once the jump is done
+    closes: bool,
+    started: Instant,
+}
+
+/// The outcome of a callback task, a panic in it becomes an error so the terminal is still
+/// restored on the way out.
+fn joined(result: std::result::Result<Result<()>, tokio::task::JoinError>) -> Result<()> {
+    result.map_err(Error::Jump)?
 }
 
-impl IsRelevant for Item {
-    fn is_relevant(&self) -> bool {
-        !matches!(
-            self,
-            Item::TraitAlias(_) | Item::Verbatim(_) | Item::ForeignMod(_) | Item::ExternCrate(_)
-        )
+/// Most results sent to the quickfix list, neovim gets slow with huge lists.
+const QUICKFIX_LIMIT: usize = 1000;
+
+/// How much less a doc comment match counts than a match of the signature.
+const DOC_SCORE_DIVISOR: i64 = 2;
+
+/// How much Alt-Up/Down change the minimum score by.
+const MIN_SCORE_STEP: i64 = 10;
+
+/// Where a result ranks, higher first: by its score, then by how early in the signature the
+/// query starts matching, so `new` puts `fn new_in` ahead of `fn renew` when both score the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Priority {
+    pub score: i64,
+    /// Char offset of the first matched char in the signature, alias or doc comment that scored
+    /// best, 0 without a query or when the tie-break is off
+    pub match_start: Reverse<usize>,
+}
+
+/// How well the query matches one text, higher first: by score, then by how early it starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct TextMatch {
+    score: i64,
+    /// Char offset of the first matched char
+    start: Reverse<usize>,
+}
+
+/// Tie-break order for results the matcher scores equally, e.g. every ref for an empty query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Alphabetically by name
+    #[default]
+    Name,
+    /// By file, then position within the file
+    File,
+    /// By item kind, then name
+    Kind,
+    /// Longest items first, then name
+    Lines,
+    /// Most recently modified files first, then position within the file
+    Modified,
+}
+
+impl SortOrder {
+    /// How `a` and `b` are ordered, looking up when their files were modified in `mtimes`.
+    pub fn compare(&self, a: &Ref, b: &Ref, mtimes: &Mtimes) -> Ordering {
+        let location = |r: &Ref| (r.file.to_owned(), r.line, r.column);
+        match self {
+            SortOrder::Name => a
+                .name
+                .cmp(&b.name)
+                .then_with(|| location(a).cmp(&location(b))),
+            SortOrder::File => location(a).cmp(&location(b)),
+            SortOrder::Kind => a
+                .kind
+                .cmp(&b.kind)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| location(a).cmp(&location(b))),
+            SortOrder::Lines => b
+                .line_count()
+                .cmp(&a.line_count())
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| location(a).cmp(&location(b))),
+            SortOrder::Modified => mtimes
+                .get(&b.file)
+                .cmp(&mtimes.get(&a.file))
+                .then_with(|| location(a).cmp(&location(b))),
+        }
+    }
+
+    /// The order after this one, wrapping around.
+    pub fn next(&self) -> Self {
+        match self {
+            SortOrder::Name => SortOrder::File,
+            SortOrder::File => SortOrder::Kind,
+            SortOrder::Kind => SortOrder::Lines,
+            SortOrder::Lines => SortOrder::Modified,
+            SortOrder::Modified => SortOrder::Name,
+        }
+    }
+}
+
+/// When files were last modified, read the first time each one is asked about as only sorting
+/// by it needs them. Files that can't be read count as the oldest.
+#[derive(Debug, Default)]
+pub struct Mtimes(RefCell<HashMap<PathBuf, Option<SystemTime>>>);
+
+impl Mtimes {
+    pub fn get(&self, file: &Path) -> Option<SystemTime> {
+        if let Some(mtime) = self.0.borrow().get(file) {
+            return *mtime;
+        }
+        let mtime = fs::metadata(file).and_then(|meta| meta.modified()).ok();
+        self.0.borrow_mut().insert(file.to_owned(), mtime);
+        mtime
+    }
+
+    /// Forget every time read so far, e.g. once the files may have been edited.
+    pub fn clear(&mut self) {
+        self.0.get_mut().clear();
+    }
+}
+
+/// Most files [`Sources`] keeps, enough for both panes of a comparison.
+const CACHED_SOURCES: usize = 2;
+
+/// The contents of the files previewed last, so the preview doesn't read its file again on
+/// every frame. A file is read again once it has been modified.
+#[derive(Debug, Default)]
+pub struct Sources(RefCell<Vec<Source>>);
+
+/// A file's contents as of when it was last modified.
+#[derive(Debug)]
+struct Source {
+    file: PathBuf,
+    mtime: Option<SystemTime>,
+    text: Rc<str>,
+}
+
+impl Sources {
+    pub fn get(&self, file: &Path) -> io::Result<Rc<str>> {
+        let mtime = fs::metadata(file)?.modified().ok();
+        let mut cached = self.0.borrow_mut();
+        if let Some(source) = cached
+            .iter()
+            .find(|source| source.file == file && source.mtime == mtime)
+        {
+            return Ok(source.text.clone());
+        }
+        let text: Rc<str> = fs::read_to_string(file)?.into();
+        cached.retain(|source| source.file != file);
+        if cached.len() >= CACHED_SOURCES {
+            cached.remove(0);
+        }
+        cached.push(Source {
+            file: file.to_owned(),
+            mtime,
+            text: text.clone(),
+        });
+        Ok(text)
+    }
+}
+
+/// The refs a query matched, regardless of the minimum score, along with the filters they were
+/// matched under.
+struct Narrowed {
+    input: String,
+    public_only: bool,
+    outline: Option<PathBuf>,
+    search_docs: bool,
+    refs: Vec<Ref>,
+}
+
+impl Narrowed {
+    /// Whether every ref the app's query matches is among these, i.e. the query only grew at
+    /// the end under the same filters. Operators change what the rest means, e.g. `ab$` then
+    /// `ab$c`, and so does finishing a filter like `@fn`, so they have to stay the same.
+    fn narrows_to(&self, app: &App) -> bool {
+        let (old, new) = (Query::parse(&self.input), Query::parse(&app.input));
+        app.input.starts_with(&self.input)
+            && self.public_only == app.public_only
+            && self.outline == app.outline
+            && self.search_docs == app.search_docs
+            && (old.kind, old.public) == (new.kind, new.public)
+            && (old.exact, old.prefix, old.suffix) == (new.exact, new.prefix, new.suffix)
+            && !old.suffix
     }
 }
 
-pub trait ItemDisplay {
-    fn display(&self) -> String;
+/// The pane navigation keys act on. Typing always goes to the input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    Input,
+    Results,
+    Preview,
 }
 
-impl ItemDisplay for Item {
-    fn display(&self) -> String {
+impl Focus {
+    /// The pane after this one, wrapping around.
+    pub fn next(&self) -> Self {
         match self {
-            Item::Fn(item) => {
-                format!(
-                    "{}{}",
-                    item.vis
-                        .span()
-                        .source_text()
-                        .map_or(String::new(), |e| e + " "),
-                    item.sig
-                        .span()
-                        .source_text()
-                        .unwrap_or("MISSING SOURCE TEXT".to_string())
-                )
-            }
-            Item::Mod(item) => {
-                format!(
-                    "{}mod {}",
-                    item.vis
-                        .span()
-                        .source_text()
-                        .map_or(String::new(), |e| e + " "),
-                    item.ident
-                )
-            }
-            Item::Enum(item) => {
-                format!(
-                    "{}enum {}",
-                    item.vis
-                        .span()
-                        .source_text()
-                        .map_or(String::new(), |e| e + " "),
-                    item.ident
-                )
-            }
-            Item::Trait(item) => {
-                format!(
-                    "{}trait {}",
-                    item.vis
-                        .span()
-                        .source_text()
-                        .map_or(String::new(), |e| e + " "),
-                    item.ident
-                )
-            }
-            Item::Struct(item) => {
-                format!(
-                    "{}struct {}",
-                    item.vis
-                        .span()
-                        .source_text()
-                        .map_or(String::new(), |e| e + " "),
-                    item.ident
-                )
-            }
-            Item::Use(item) => item.span().source_text().unwrap_or(String::from("UNKNOWN")),
-            Item::Type(item) => item.span().source_text().unwrap_or(String::from("UNKNOWN")),
-            Item::Impl(item) => {
-                if let Some((_, pth, _)) = &item.trait_ {
-                    format!(
-                        "impl {} for {}",
-                        pth.segments
-                            .last()
-                            .span()
-                            .source_text()
-                            .unwrap_or("UNKNOWN".into()),
-                        item.self_ty
-                            .span()
-                            .source_text()
-                            .unwrap_or("UNKNOWN".into())
-                    )
-                } else {
-                    format!(
-                        "impl {}",
-                        item.self_ty
-                            .span()
-                            .source_text()
-                            .unwrap_or("UNKNOWN".into())
-                    )
-                }
-            }
-            Item::Const(item) => item.span().source_text().unwrap_or("UNKNOWN".into()),
-            Item::Macro(item) => item.ident.span().source_text().unwrap_or("UNKNOWN".into()),
-            Item::Static(item) => item.span().source_text().unwrap_or("UNKNOWN".into()),
-            Item::Union(item) => {
-                format!(
-                    "{}union {}",
-                    item.vis.span().source_text().unwrap_or("".into()),
-                    item.ident.span().source_text().unwrap_or("UNKNOWN".into())
-                )
-            }
-            _ => "IRRELEVANT".into(),
+            Focus::Input => Focus::Results,
+            Focus::Results => Focus::Preview,
+            Focus::Preview => Focus::Input,
+        }
+    }
+
+    /// The pane before this one, wrapping around.
+    pub fn previous(&self) -> Self {
+        match self {
+            Focus::Input => Focus::Preview,
+            Focus::Results => Focus::Input,
+            Focus::Preview => Focus::Results,
         }
     }
 }
 
-pub trait SelectCallback {
-    fn call(&self, selection: Ref) -> BoxFuture<'static, Result<()>>;
+/// Which fuzzy matching algorithm scores refs against the query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Matcher {
+    /// The matcher of clangd's code completion
+    #[default]
+    Clangd,
+    /// The matcher of skim, weighing matches at word starts more heavily
+    Skim,
 }
 
-impl<T, F> SelectCallback for T
-where
-    T: Fn(Ref) -> F,
-    F: Future<Output = Result<()>> + 'static + Send,
-{
-    fn call(&self, selection: Ref) -> BoxFuture<'static, Result<()>> {
-        Box::pin(self(selection))
+impl Matcher {
+    pub fn build(&self) -> Box<dyn FuzzyMatcher> {
+        match self {
+            Matcher::Clangd => Box::new(ClangdMatcher::default()),
+            Matcher::Skim => Box::new(SkimMatcherV2::default()),
+        }
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matcher::Clangd => write!(f, "clangd"),
+            Matcher::Skim => write!(f, "skim"),
+        }
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortOrder::Name => write!(f, "name"),
+            SortOrder::File => write!(f, "file"),
+            SortOrder::Kind => write!(f, "kind"),
+            SortOrder::Lines => write!(f, "lines"),
+            SortOrder::Modified => write!(f, "modified"),
+        }
+    }
+}
+
+/// Where the event loop gets its input from, and how it lends the terminal to other programs.
+/// [`App::run`] reads the real terminal through [`Crossterm`], anything else can drive
+/// [`App::run_with`], e.g. scripted keys against a `TestBackend`.
+pub trait EventSource<B: Backend> {
+    /// The next event if one arrives within `timeout`.
+    fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>>;
+
+    /// Hand the terminal to another program, e.g. one opening a file in the terminal.
+    fn suspend(&mut self, _terminal: &mut Terminal<B>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Take the terminal back after [`EventSource::suspend`].
+    fn resume(&mut self, _terminal: &mut Terminal<B>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Scripted events, e.g. `[Event::Key(KeyCode::Down.into())].into_iter()`. Once they run out
+/// every further event is Esc, so the loop ends.
+impl<B: Backend, I: Iterator<Item = Event>> EventSource<B> for I {
+    fn next_event(&mut self, _timeout: Duration) -> Result<Option<Event>> {
+        Ok(Some(self.next().unwrap_or(Event::Key(KeyCode::Esc.into()))))
+    }
+}
+
+/// A read interrupted by a signal, e.g. `SIGWINCH` while resizing, is retried on the next turn
+/// of the loop as if no event came rather than ending the app, any other error is fatal.
+fn retry_interrupted(event: io::Result<Option<Event>>) -> Result<Option<Event>> {
+    match event {
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+            debug!("Terminal read interrupted: {err}");
+            Ok(None)
+        }
+        event => Ok(event?),
+    }
+}
+
+/// The real terminal, in `inline` mode if drawing below the prompt.
+pub struct Crossterm {
+    pub inline: bool,
+}
+
+impl EventSource<CrosstermBackend<Stderr>> for Crossterm {
+    fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        retry_interrupted(
+            event::poll(timeout).and_then(|ready| ready.then(event::read).transpose()),
+        )
+    }
+
+    fn suspend(&mut self, terminal: &mut Terminal<CrosstermBackend<Stderr>>) -> Result<()> {
+        suspend(terminal, self.inline)
+    }
+
+    fn resume(&mut self, terminal: &mut Terminal<CrosstermBackend<Stderr>>) -> Result<()> {
+        resume(terminal, self.inline)
+    }
+}
+
+/// Hand the terminal back, leaving the alternate screen and raw mode. An `inline` viewport is
+/// cleared instead, leaving the cursor where it started so the shell carries on from there.
+fn suspend(terminal: &mut Terminal<CrosstermBackend<Stderr>>, inline: bool) -> Result<()> {
+    disable_raw_mode()?;
+    if inline {
+        terminal.clear()?;
+        let top = terminal.get_frame().area().y;
+        terminal.set_cursor_position(Position::new(0, top))?;
+        execute!(terminal.backend_mut(), DisableMouseCapture)?;
+    } else {
+        execute!(
+            terminal.backend_mut(),
+            LeaveAlternateScreen,
+            DisableMouseCapture
+        )?;
+    }
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+/// Take the terminal back after `suspend`, redrawing everything on the next frame.
+fn resume(terminal: &mut Terminal<CrosstermBackend<Stderr>>, inline: bool) -> Result<()> {
+    enable_raw_mode()?;
+    if inline {
+        execute!(terminal.backend_mut(), EnableMouseCapture)?;
+    } else {
+        execute!(
+            terminal.backend_mut(),
+            EnterAlternateScreen,
+            EnableMouseCapture
+        )?;
+    }
+    terminal.clear()?;
+    Ok(())
+}
+
+/// A line of the results list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Row {
+    Ref(Ref),
+    /// Header of several refs sharing a name, listed below it as members once expanded
+    Group {
+        name: String,
+        count: usize,
+        expanded: bool,
+    },
+    /// A ref listed under its expanded group
+    Member(Ref),
+    /// Header of the results of one kind, which the selection skips
+    Section {
+        kind: RefKind,
+        count: usize,
+    },
+}
+
+impl Row {
+    /// The ref this row stands for, headers don't have one.
+    pub fn target(&self) -> Option<&Ref> {
+        match self {
+            Row::Ref(r) | Row::Member(r) => Some(r),
+            Row::Group { .. } | Row::Section { .. } => None,
+        }
+    }
+
+    /// Whether the selection can rest on this row, everything but section headers.
+    pub fn is_selectable(&self) -> bool {
+        !matches!(self, Row::Section { .. })
     }
 }
 
 pub struct App {
     pub refs: Vec<Ref>,
-    pub search_results: PriorityQueue<Ref, i64>,
+    pub search_results: PriorityQueue<Ref, Priority>,
+    /// What the last query matched, to search within when it is extended
+    narrowed: Option<Narrowed>,
     pub input: String,
+    /// Byte offset of the cursor in `input`, always on a char boundary
+    pub cursor: usize,
     pub search_result_state: ListState,
+    /// Refs marked with Tab, in the order they were marked
+    pub marked: Vec<Ref>,
     pub select_callback: Option<Box<dyn SelectCallback>>,
+    /// The jump the select callback is busy with, if any
+    pending: Option<Pending>,
+    /// How long a jump may take before it is given up on, see [`DEFAULT_CALLBACK_TIMEOUT`]
+    pub callback_timeout: Duration,
+    /// What Enter does with the selection before closing the picker
+    pub enter_action: Action,
+    /// What Ctrl-Enter and Alt-Enter do with the selection, keeping the picker open
+    pub alt_enter_action: Action,
+    /// Refs selected with [`Action::Print`], for the caller to print once the picker is closed
+    pub printed: Vec<Ref>,
+    /// Called with the current results to fill the quickfix list, on Ctrl-q
+    pub quickfix_callback: Option<Box<dyn SelectCallback>>,
+    /// Called with the selected ref to copy its item into a scratch buffer, on Ctrl-b
+    pub scratch_callback: Option<Box<dyn SelectCallback>>,
+    /// What is indexed, kept around to index it again on reload
+    root: PathBuf,
+    files: Option<Vec<PathBuf>>,
+    opts: IndexOptions,
+    /// Refs parsed by the background indexer, `None` once indexing has finished
+    pub indexer: Option<Receiver<Indexed>>,
+    /// Files the indexer failed to parse so far
+    failed: usize,
+    /// Whether the running indexer was started by a reload
+    reloading: bool,
+    /// The ref selected before a reload, selected again once it has been reindexed
+    preserved: Option<Ref>,
+    /// A short message for the footer, cleared on the next key press
+    pub status: Option<String>,
+    /// Opened on the first copy and kept, on X11 the copied text is gone once it is dropped
+    clipboard: Option<Clipboard>,
+    /// How many of the discovered files the background indexer has parsed
+    pub progress: Progress,
+    /// Only show `pub` and `pub(crate)` items
+    pub public_only: bool,
+    /// Only show the refs of this file, an outline of it, toggle with Alt-o
+    pub outline: Option<PathBuf>,
+    /// How results with equal scores are ordered
+    pub sort: SortOrder,
+    /// When the files of the refs were modified, for [`SortOrder::Modified`]
+    mtimes: Mtimes,
+    /// The files shown in the preview, see [`Sources`]
+    pub sources: Sources,
+    /// Most results listed, the rest still match but aren't shown, 0 lists all of them
+    pub max_results: usize,
+    /// Scores refs against the query
+    pub matcher: Box<dyn FuzzyMatcher>,
+    /// Matches scoring below this are hidden, unless the query is empty
+    pub min_score: i64,
+    /// Rank equally scored matches by where in the signature they start, see [`Priority`]
+    pub position_tie_break: bool,
+    /// Added to the score of refs of each kind, see [`default_kind_weights`]
+    pub kind_weights: HashMap<RefKind, i64>,
+    /// Recently selected refs, ranked above others
+    pub history: History,
+    /// Whether the key and query syntax reference is shown, toggle with F1
+    pub show_help: bool,
+    /// Colors the TUI is drawn with
+    pub theme: Theme,
+    /// The pane Up/Down act on, cycle with Ctrl-l and Ctrl-h
+    pub focus: Focus,
+    /// Lines the preview is scrolled down by, back to the top whenever the selection changes
+    pub preview_scroll: u16,
+    /// Show how many lines each result's item spans, toggle with Ctrl-n
+    pub show_line_counts: bool,
+    /// Match the query against doc comments too, toggle with Ctrl-d
+    pub search_docs: bool,
+    /// Show each result's file after its signature, toggle with Ctrl-f
+    pub show_paths: bool,
+    /// Mark where the query occurs in the preview, toggle with Ctrl-t
+    pub highlight_query: bool,
+    /// Collapse refs sharing a name under a header, toggle with Ctrl-g
+    pub grouped: bool,
+    /// List the results in sections per kind, e.g. all functions then all structs, toggle with
+    /// Alt-k
+    pub by_kind: bool,
+    /// Names of the groups showing their members
+    pub expanded: HashSet<String>,
+    /// Lines of source shown above and below the symbol's item in the preview, if unset a few
+    /// above and a couple below
+    pub context: Option<usize>,
+    /// Highlight the preview's syntax, otherwise it is plain text
+    pub preview_highlight: bool,
+    /// Wrap long lines in the preview at its width rather than cutting them off, toggle with
+    /// Alt-w
+    pub preview_wrap: bool,
+    /// Lines at the bottom of the terminal to draw in instead of taking over the whole screen
+    pub inline: Option<u16>,
+    /// URL of a location for markdown links copied with Alt-l, see [`markdown_link`]
+    pub link_template: Option<String>,
+    /// Whether anything on screen changed since the last draw
+    dirty: bool,
+    /// Number of loop iterations so far, drives the indexing spinner
+    pub tick: usize,
 }
 
-impl App {
-    pub fn new() -> Result<Self> {
-        // Parse all of our rust files
-        let refs = App::find_refs()?;
+/// Refs of one file from the background indexer, `None` if it failed to parse.
+type Indexed = (Progress, Option<Vec<Ref>>);
+
+/// Parse all of our rust files in the background so the TUI opens immediately.
+fn spawn_indexer(
+    root: PathBuf,
+    files: Option<Vec<PathBuf>>,
+    opts: IndexOptions,
+) -> Receiver<Indexed> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut indexer = match files {
+            Some(files) => Indexer::with_files(&root, files, opts),
+            None => Indexer::new(&root, opts),
+        };
+        if tx.send((indexer.progress(), Some(Vec::new()))).is_err() {
+            return;
+        }
+        while let Some((file, refs)) = indexer.next() {
+            let refs = refs
+                .inspect_err(|err| error!("Skipping {}: {}", file.display(), err))
+                .ok();
+            debug!("refs: {:#?}", refs);
+            // The app has gone away, nobody is left to index for
+            if tx.send((indexer.progress(), refs)).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+/// Configures an `App` before it starts indexing.
+#[derive(Default)]
+pub struct AppBuilder {
+    root: Option<PathBuf>,
+    files: Option<Vec<PathBuf>>,
+    opts: IndexOptions,
+    matcher: Matcher,
+    query: String,
+}
+
+impl AppBuilder {
+    /// Directory to index, the current directory by default.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Index exactly these files instead of discovering the ones below the root.
+    pub fn files(mut self, files: Vec<PathBuf>) -> Self {
+        self.files = Some(files);
+        self
+    }
+
+    /// What to index, e.g. which extensions and whether to descend into hidden files.
+    pub fn options(mut self, opts: IndexOptions) -> Self {
+        self.opts = opts;
+        self
+    }
 
-        let search_results = refs.iter().map(|elem| (elem.to_owned(), 0)).collect();
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
 
-        debug!("refs: {:#?}", refs);
+    /// Query to start out with, as if it had been typed.
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.query = query.into();
+        self
+    }
 
-        Ok(Self {
-            refs,
-            search_results,
-            input: String::new(),
+    pub fn build(self) -> Result<App> {
+        let root = match self.root {
+            Some(root) => root,
+            None => env::current_dir()?,
+        };
+        // Absolute so the files of refs compare equal to the names of the buffers open in nvim
+        let root = root.canonicalize()?;
+        let history = History::load(&root);
+        Ok(App {
+            indexer: Some(spawn_indexer(
+                root.clone(),
+                self.files.clone(),
+                self.opts.clone(),
+            )),
+            root,
+            files: self.files,
+            opts: self.opts,
+            failed: 0,
+            status: None,
+            clipboard: None,
+            reloading: false,
+            preserved: None,
+            refs: Vec::new(),
+            search_results: PriorityQueue::new(),
+            narrowed: None,
+            cursor: self.query.len(),
+            input: self.query,
             search_result_state: ListState::default(),
+            marked: Vec::new(),
             select_callback: None,
+            pending: None,
+            callback_timeout: DEFAULT_CALLBACK_TIMEOUT,
+            enter_action: Action::default(),
+            alt_enter_action: Action::default(),
+            printed: Vec::new(),
+            quickfix_callback: None,
+            scratch_callback: None,
+            progress: Progress::default(),
+            public_only: false,
+            outline: None,
+            sort: SortOrder::default(),
+            mtimes: Mtimes::default(),
+            sources: Sources::default(),
+            max_results: 0,
+            matcher: self.matcher.build(),
+            min_score: 0,
+            position_tie_break: true,
+            history,
+            kind_weights: default_kind_weights(),
+            link_template: None,
+            context: None,
+            preview_highlight: true,
+            preview_wrap: false,
+            inline: None,
+            dirty: true,
+            highlight_query: true,
+            show_paths: false,
+            search_docs: false,
+            show_line_counts: false,
+            focus: Focus::default(),
+            theme: Theme::default(),
+            show_help: false,
+            preview_scroll: 0,
+            grouped: false,
+            by_kind: false,
+            expanded: HashSet::new(),
+            tick: 0,
         })
     }
+}
 
-    fn recursive_find_refs(item: Item, refs: &mut Vec<Ref>, file: &Path) -> Result<()> {
-        // Push the item itself
-        if !item.is_relevant() {
-            return Ok(());
-        }
-        refs.push((item.clone(), file.to_owned()).into());
-        match item {
-            Item::Mod(md) => {
-                // If the module has a body
-                if let Some(content) = md.content {
-                    // For every item in the module
-                    for item in content.1 {
-                        Self::recursive_find_refs(item, refs, file)?;
+impl App {
+    pub fn builder() -> AppBuilder {
+        AppBuilder::default()
+    }
+
+    /// An app indexing the current directory with the default options.
+    // The binary configures its app through the builder, this is the shorthand for the rest
+    #[allow(dead_code)]
+    pub fn new() -> Result<App> {
+        App::builder().build()
+    }
+
+    /// The directory being indexed.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Move any refs the background indexer has produced into the index, scoring them against
+    /// the active query while keeping the current selection on the same ref.
+    fn receive_refs(&mut self) {
+        let Some(indexer) = &self.indexer else {
+            return;
+        };
+
+        let mut received = Vec::new();
+        loop {
+            match indexer.try_recv() {
+                Ok((progress, refs)) => {
+                    self.progress = progress;
+                    match refs {
+                        Some(refs) => received.extend(refs),
+                        None => self.failed += 1,
                     }
                 }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.indexer = None;
+                    // Draw once more to take the spinner down
+                    self.dirty = true;
+                    break;
+                }
+            }
+        }
+        if self.indexer.is_none() && self.reloading {
+            self.reloading = false;
+            self.preserved = None;
+            self.status = Some(match self.failed {
+                0 => "reindexed".into(),
+                failed => format!("reindexed, {failed} file(s) failed to parse"),
+            });
+        }
+        if received.is_empty() {
+            return;
+        }
+
+        let selected = match &self.preserved {
+            // The symbol may have moved in its file, find it by where it lives instead
+            Some(preserved) => {
+                let found = received
+                    .iter()
+                    .find(|r| {
+                        r.file == preserved.file
+                            && r.kind == preserved.kind
+                            && r.path() == preserved.path()
+                    })
+                    .cloned();
+                if found.is_some() {
+                    self.preserved = None;
+                }
+                found
             }
-            // For now, ignore implement items, will require rework of ref struct
-            Item::Impl(_im) => {
-                //for item in im.items {
-                //    match item {
-                //        ImplItem::Fn(fun) => {
-                //
-                //        }
-                //    }
-                //    Self::recursive_find_refs(item, refs, file);
-                //}
+            None => self.get_selected_ref(),
+        };
+        let query = Query::parse(&self.input);
+        let now = history::now();
+        for r in &received {
+            if !self.is_shown(r) || !query.admits(r) {
+                continue;
+            }
+            let Some(found) = self.match_score(&query, r) else {
+                continue;
+            };
+            if let Some(narrowed) = &mut self.narrowed {
+                narrowed.refs.push(r.to_owned());
+            }
+            if let Some(rank) = self.rank(&query, r, found, now) {
+                self.search_results.push(r.to_owned(), rank);
             }
-            _ => {}
         }
-        Ok(())
+        self.refs.extend(received);
+
+        self.reselect(selected);
+        self.select_first();
     }
 
-    fn find_refs() -> Result<Vec<Ref>> {
-        let files: Vec<PathBuf> = SearchBuilder::default()
-            .location(env::current_dir().unwrap())
-            .ext("rs")
-            .hidden()
-            .build()
-            .map(PathBuf::from)
-            .collect();
+    /// Point the selection back at `selected` after the results have been reordered, or at its
+    /// group if that is collapsed.
+    fn reselect(&mut self, selected: Option<Ref>) {
+        if let Some(selected) = selected {
+            let rows = self.rows();
+            let i = rows
+                .iter()
+                .position(|row| row.target() == Some(&selected))
+                .or_else(|| {
+                    rows.iter().position(
+                        |row| matches!(row, Row::Group { name, .. } if *name == selected.name),
+                    )
+                });
+            self.search_result_state.select(i);
+        }
+    }
 
-        let mut refs = Vec::<Ref>::new();
-        for file in files {
-            let src = fs::read_to_string(&file)?;
-            let syntax = syn::parse_file(&src)?;
-            // Append refs with each item in the file that is relevant
-            for item in syntax.items {
-                Self::recursive_find_refs(item, &mut refs, &file)?;
-            }
+    /// Expand or collapse the group of the selected row, returning whether there was one.
+    /// Collapsing from a member moves the selection up to its header.
+    fn set_expanded(&mut self, expand: bool) -> bool {
+        let name = match self.get_selected_row() {
+            Some(Row::Group { name, .. }) => name,
+            Some(Row::Member(r)) if !expand => r.name,
+            _ => return false,
+        };
+        if expand {
+            self.expanded.insert(name);
+        } else {
+            self.expanded.remove(&name);
+            let i = self
+                .rows()
+                .iter()
+                .position(|row| matches!(row, Row::Group { name: group, .. } if *group == name));
+            self.search_result_state.select(i);
         }
+        true
+    }
 
-        Ok(refs)
+    /// Type a character into the query at the cursor.
+    fn insert_char(&mut self, ch: char) {
+        self.input.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+        self.recompute_results();
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        enable_raw_mode()?;
-        let mut stderr = io::stderr();
-        execute!(stderr, EnterAlternateScreen, EnableMouseCapture)?;
+    /// Whether a ref passes the active view filters, independent of the query.
+    fn is_shown(&self, r: &Ref) -> bool {
+        (!self.public_only || r.visibility.is_exported())
+            && self.outline.as_ref().is_none_or(|file| r.file == *file)
+    }
 
-        let backend = CrosstermBackend::new(stderr);
-        let mut terminal = Terminal::new(backend)?;
+    /// How well a ref matches the query, by its signature or whichever alias matches best.
+    fn match_score(&self, query: &Query, r: &Ref) -> Option<TextMatch> {
+        if query.pattern.is_empty() {
+            return Some(TextMatch {
+                score: 0,
+                start: Reverse(0),
+            });
+        }
+        let (name_match, doc_match) = self.match_scores(query, r);
+        name_match.max(doc_match)
+    }
 
-        loop {
-            terminal.draw(|f| tui::ui(f, self))?;
-            if let Event::Key(key) = event::read()? {
-                if key.kind == event::KeyEventKind::Release {
-                    continue;
-                }
+    /// Where a ref matching as `found` ranks, nudged up if it was selected recently as of
+    /// `now`, or `None` if it falls below the minimum score.
+    fn rank(&self, query: &Query, r: &Ref, found: TextMatch, now: u64) -> Option<Priority> {
+        // Without a query everything matches equally, leaving the order to history, kind and
+        // `sort`
+        (query.pattern.is_empty() || found.score >= self.min_score).then(|| Priority {
+            score: found.score
+                + self.history.boost(r, now)
+                + self.kind_weights.get(&r.kind).copied().unwrap_or(0),
+            match_start: if self.position_tie_break {
+                found.start
+            } else {
+                Reverse(0)
+            },
+        })
+    }
 
-                if key.modifiers == KeyModifiers::CONTROL {
-                    // Allow Ctrl-j/k to move up and down selection
-                    if let KeyCode::Char(ch) = key.code {
-                        match ch {
-                            'j' => self.search_result_state.select_next(),
-                            'k' => self.search_result_state.select_previous(),
-                            _ => {}
-                        }
-                    }
-                } else if key.modifiers == KeyModifiers::SHIFT {
-                    // Allow Shift+Tab to move up selection
-                    if let KeyCode::BackTab = key.code {
-                        self.search_result_state.select_previous();
-                    }
-                } else if key.modifiers == KeyModifiers::NONE {
-                    // All other normal keybinds
-                    match key.code {
-                        KeyCode::Esc => break,
-                        KeyCode::Char(ch) => {
-                            // Every time the user types a character, add it to the input, drain the refs,
-                            // map them to assign new prio, then collect and reassign them to refs.
-                            self.input.push(ch);
-                            self.search_results = self
-                                .refs
-                                .iter()
-                                .filter_map(|elem| {
-                                    fuzzy_match(&elem.sig, &self.input)
-                                        .map(|prio| (elem.to_owned(), prio))
-                                })
-                                .collect()
-                        }
-                        KeyCode::Up => self.search_result_state.select_previous(),
-                        KeyCode::BackTab => self.search_result_state.select_previous(),
-                        KeyCode::Down => self.search_result_state.select_next(),
-                        KeyCode::Tab => self.search_result_state.select_next(),
-                        KeyCode::Backspace => {
-                            self.input.pop();
-                            self.search_results = self
-                                .refs
-                                .iter()
-                                .map(|elem| {
-                                    (
-                                        elem.to_owned(),
-                                        fuzzy_match(&elem.sig, &self.input).unwrap_or_default(),
-                                    )
-                                })
-                                .collect();
-                        }
-                        KeyCode::Enter => {
-                            // Continue if nothing is selected
-                            if let Some(r) = self.get_selected_ref() {
-                                if let Some(callback) = &self.select_callback {
-                                    callback.call(r.clone()).await?;
-                                }
-                                break;
-                            } else {
-                                continue;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            sleep(Duration::from_millis(25));
-        }
-        disable_raw_mode()?;
-        execute!(
-            terminal.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        )?;
-        terminal.show_cursor()?;
+    /// The query's match of the signature and aliases, and of the doc comment when searching
+    /// docs. Doc scores are scaled down so a name match still ranks first.
+    fn match_scores(&self, query: &Query, r: &Ref) -> (Option<TextMatch>, Option<TextMatch>) {
+        let name_match = std::iter::once(&r.match_sig)
+            .chain(&r.aliases)
+            .filter_map(|text| self.match_text(query, text))
+            .max();
+        let doc_match = r
+            .docs
+            .as_ref()
+            .filter(|_| self.search_docs)
+            .and_then(|docs| self.match_text(query, docs))
+            .map(|found| TextMatch {
+                score: found.score / DOC_SCORE_DIVISOR,
+                ..found
+            });
+        (name_match, doc_match)
+    }
 
-        Ok(())
+    /// How well the query matches one text, if it does at all. The matched chars come with the
+    /// score, so where the match starts costs no second pass.
+    fn match_text(&self, query: &Query, text: &str) -> Option<TextMatch> {
+        let scored = || {
+            self.matcher
+                .fuzzy_indices(text, query.pattern)
+                .map(|(score, indices)| TextMatch {
+                    score,
+                    start: Reverse(indices.first().copied().unwrap_or(usize::MAX)),
+                })
+        };
+        if query.is_fuzzy() {
+            return scored();
+        }
+        // Exact matches are still ranked by the matcher, e.g. to prefer word boundaries
+        query.matches(text).then(|| {
+            scored().unwrap_or(TextMatch {
+                score: 0,
+                start: Reverse(usize::MAX),
+            })
+        })
     }
 
-    pub fn get_selected_ref(&self) -> Option<Ref> {
+    /// Whether a ref is in the results of `query` for its doc comment rather than its name.
+    pub fn matched_docs(&self, query: &Query, r: &Ref) -> bool {
+        if query.pattern.is_empty() {
+            return false;
+        }
+        match self.match_scores(query, r) {
+            (name_match, Some(doc_match)) => {
+                name_match.is_none_or(|name_match| name_match.score < doc_match.score)
+            }
+            (_, None) => false,
+        }
+    }
+
+    /// Rescore every shown ref against the query, dropping the ones that don't match. Every edit
+    /// of the input goes through here so the results only depend on the query, not on how it
+    /// was typed. When the query was only extended just the refs the last one matched are
+    /// rescored, the others can't match either.
+    fn recompute_results(&mut self) {
+        let narrowed = self.narrowed.take();
+        let candidates = match &narrowed {
+            Some(narrowed) if narrowed.narrows_to(self) => &narrowed.refs,
+            _ => &self.refs,
+        };
+        let query = Query::parse(&self.input);
+        let now = history::now();
+        let mut matched = Vec::new();
+        let mut search_results = PriorityQueue::new();
+        for r in candidates
+            .iter()
+            .filter(|r| self.is_shown(r) && query.admits(r))
+        {
+            let Some(found) = self.match_score(&query, r) else {
+                continue;
+            };
+            matched.push(r.to_owned());
+            if let Some(rank) = self.rank(&query, r, found, now) {
+                search_results.push(r.to_owned(), rank);
+            }
+        }
+        self.search_results = search_results;
+        self.narrowed = Some(Narrowed {
+            input: self.input.clone(),
+            public_only: self.public_only,
+            outline: self.outline.clone(),
+            search_docs: self.search_docs,
+            refs: matched,
+        });
+        self.select_first();
+    }
+
+    /// Select the best match if nothing is selected, or only a section header, so its preview
+    /// shows without having to move there first. A selection left past the end by narrowing the
+    /// results moves up to the last row. A selection that is held, e.g. while a reload looks for
+    /// the symbol it was on, is left alone.
+    fn select_first(&mut self) {
+        if self.search_results.is_empty() {
+            return;
+        }
+        if self.preserved.is_none() {
+            let count = self.row_count();
+            if self
+                .search_result_state
+                .selected()
+                .is_some_and(|i| i >= count)
+            {
+                self.search_result_state.select(count.checked_sub(1));
+            }
+        }
+        let selected = self.search_result_state.selected();
+        if self.by_kind {
+            let on_header = selected
+                .is_some_and(|i| self.rows().get(i).is_some_and(|row| !row.is_selectable()));
+            if selected.is_none() || on_header {
+                // The best match, which needn't be in the first section
+                let best = self.ranked_results().into_iter().next();
+                self.reselect(best);
+            }
+        } else if selected.is_none() {
+            self.search_result_state.select(Some(0));
+        }
+    }
+
+    /// Put `text` on the clipboard, reporting in the footer whether that worked. Without a
+    /// clipboard, e.g. over ssh, only the footer says so.
+    fn copy(&mut self, what: &str, text: String) {
+        let clipboard = match self.clipboard.take() {
+            Some(clipboard) => Ok(clipboard),
+            None => Clipboard::new(),
+        };
+        self.status = Some(match clipboard {
+            Ok(mut clipboard) => {
+                let copied = clipboard.set_text(text);
+                self.clipboard = Some(clipboard);
+                match copied {
+                    Ok(()) => format!("copied {what}"),
+                    Err(err) => format!("failed to copy {what}: {err}"),
+                }
+            }
+            Err(err) => format!("clipboard unavailable: {err}"),
+        });
+    }
+
+    /// Throw away the index and build it again in the background, e.g. after editing files.
+    /// The query stays and the selection sticks to the same symbol if it is still there.
+    fn reload(&mut self) {
+        let selected = self.get_selected_ref();
+        self.indexer = Some(spawn_indexer(
+            self.root.clone(),
+            self.files.clone(),
+            self.opts.clone(),
+        ));
+        self.refs.clear();
+        self.search_results.clear();
+        self.narrowed = None;
+        self.marked.clear();
+        self.mtimes.clear();
+        self.progress = Progress::default();
+        self.failed = 0;
+        self.reloading = true;
+        // Keep the selection where it was until the symbol turns up again
+        self.preserved = selected;
+    }
+
+    /// Write out state that is kept in memory while running, called on every way out of the
+    /// app. Failures are only logged so they don't hide why the app exited.
+    pub fn shutdown(&mut self) {
+        if let Err(err) = self.history.flush() {
+            error!("Failed to save history: {}", err);
+        }
+        log::logger().flush();
+    }
+
+    /// Do `action` with the marked refs, or the highlighted one without any marks. Returns what
+    /// was selected, if anything, which for the quickfix list are all the results. A jump through
+    /// the select callback returns nothing yet, it is left running as [`App::is_busy`] and closes
+    /// the picker once done if `close` says the key does, i.e. for Enter but not Alt-Enter.
+    async fn act(&mut self, action: Action, close: bool) -> Result<Option<Vec<Ref>>> {
+        if action == Action::Quickfix {
+            self.send_quickfix().await;
+            return Ok(Some(self.ranked_results()));
+        }
+        if self.pending.is_some() {
+            self.status = Some("still waiting on the editor, Esc gives up".into());
+            return Ok(None);
+        }
+        let Some(selection) = self.select() else {
+            return Ok(None);
+        };
+        match action {
+            // Done once the callback is, see `poll_pending`
+            Action::Jump => {
+                if let Some(callback) = &self.select_callback {
+                    self.pending = Some(Pending {
+                        task: tokio::spawn(callback.call(selection.clone())),
+                        selection,
+                        closes: close && action.closes(),
+                        started: Instant::now(),
+                    });
+                    return Ok(None);
+                }
+            }
+            Action::Print => self.printed.extend(selection.iter().cloned()),
+            Action::Copy => {
+                let lines: Vec<String> = selection
+                    .iter()
+                    .map(|r| location_line(r, &self.root))
+                    .collect();
+                self.copy("location", lines.join("\n"));
+            }
+            Action::Quickfix => unreachable!("handled above"),
+        }
+        Ok(Some(selection))
+    }
+
+    /// Whether the select callback is still busy with a jump.
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Check on the jump in progress, returning its selection if it is done and closes the
+    /// picker. One taking longer than `callback_timeout` is given up on, leaving the picker
+    /// open, a callback that fails ends it as before.
+    async fn poll_pending(&mut self) -> Result<Option<Vec<Ref>>> {
+        let Some(pending) = &self.pending else {
+            return Ok(None);
+        };
+        if !pending.task.is_finished() {
+            if pending.started.elapsed() >= self.callback_timeout {
+                self.cancel_pending();
+                self.status = Some(Error::Timeout(self.callback_timeout).to_string());
+                self.dirty = true;
+            }
+            return Ok(None);
+        }
+        let Some(pending) = self.pending.take() else {
+            return Ok(None);
+        };
+        self.dirty = true;
+        // Finished, so this doesn't wait
+        joined(pending.task.await)?;
+        if pending.closes {
+            return Ok(Some(pending.selection));
+        }
+        self.marked.clear();
+        Ok(None)
+    }
+
+    /// Give up on the jump in progress, it may still have happened in part.
+    fn cancel_pending(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.task.abort();
+            self.status = Some("cancelled".into());
+        }
+    }
+
+    /// Wait for the jump in progress without a picker to update, up to `callback_timeout`.
+    async fn wait_pending(&mut self) -> Result<()> {
+        let Some(pending) = self.pending.take() else {
+            return Ok(());
+        };
+        let abort = pending.task.abort_handle();
+        match tokio::time::timeout(self.callback_timeout, pending.task).await {
+            Ok(result) => joined(result),
+            Err(_) => {
+                abort.abort();
+                Err(Error::Timeout(self.callback_timeout))
+            }
+        }
+    }
+
+    /// The marked refs, or the highlighted one without any marks, recorded in the history.
+    /// Refs whose file is gone are dropped.
+    fn select(&mut self) -> Option<Vec<Ref>> {
+        let selection = if self.marked.is_empty() {
+            self.get_selected_ref().into_iter().collect()
+        } else {
+            self.marked.clone()
+        };
+        // Nothing to jump to, stop offering refs of missing files
+        let (selection, missing): (Vec<Ref>, Vec<Ref>) =
+            selection.into_iter().partition(|r| r.file.exists());
+        for r in &missing {
+            self.drop_file(&r.file);
+        }
+        if selection.is_empty() {
+            return None;
+        }
+        for r in &selection {
+            self.history.record(r);
+        }
+        Some(selection)
+    }
+
+    /// Hand the best results to the quickfix callback, reporting how that went in the footer.
+    async fn send_quickfix(&mut self) {
+        let Some(callback) = &self.quickfix_callback else {
+            self.status = Some("the quickfix list needs the nvim subcommand".into());
+            return;
+        };
+        let mut results = self.ranked_results();
+        if results.is_empty() {
+            self.status = Some("no results for the quickfix list".into());
+            return;
+        }
+        results.truncate(QUICKFIX_LIMIT);
+        let count = results.len();
+        self.status = Some(match callback.call(results).await {
+            Ok(()) => format!("sent {count} result(s) to the quickfix list"),
+            Err(err) => format!("failed to fill the quickfix list: {err}"),
+        });
+    }
+
+    /// Hand the selected ref to the scratch buffer callback, reporting how that went in the
+    /// footer.
+    async fn send_scratch(&mut self) {
+        let Some(callback) = &self.scratch_callback else {
+            self.status = Some("scratch buffers need the nvim subcommand".into());
+            return;
+        };
+        let Some(r) = self.get_selected_ref() else {
+            return;
+        };
+        self.status = Some(match callback.call(vec![r]).await {
+            Ok(()) => "copied item to a scratch buffer".into(),
+            Err(err) => format!("failed to create a scratch buffer: {err}"),
+        });
+    }
+
+    /// Mark the highlighted ref, or unmark it if it already is.
+    fn toggle_mark(&mut self) {
+        let Some(r) = self.get_selected_ref() else {
+            return;
+        };
+        if let Some(i) = self.marked.iter().position(|marked| *marked == r) {
+            self.marked.remove(i);
+        } else {
+            self.marked.push(r);
+        }
+    }
+
+    /// Forget every ref in `file`, e.g. once it turns out to have been deleted.
+    fn drop_file(&mut self, file: &Path) {
+        self.refs.retain(|r| r.file != file);
+        self.marked.retain(|r| r.file != file);
+        self.search_results.retain(|r, _| r.file != file);
+        let len = self.row_count();
+        if self
+            .search_result_state
+            .selected()
+            .is_some_and(|i| i >= len)
+        {
+            self.search_result_state.select(len.checked_sub(1));
+        }
+    }
+
+    /// Run the TUI until the user picks something, returning the selection, or cancels with
+    /// Esc, returning `None`.
+    pub async fn run(&mut self) -> Result<Option<Vec<Ref>>> {
+        enable_raw_mode()?;
+        let mut stderr = io::stderr();
+        let viewport = match self.inline {
+            Some(height) => Viewport::Inline(height),
+            None => {
+                execute!(stderr, EnterAlternateScreen)?;
+                Viewport::Fullscreen
+            }
+        };
+        execute!(stderr, EnableMouseCapture)?;
+
+        let backend = CrosstermBackend::new(stderr);
+        let mut terminal = Terminal::with_options(backend, TerminalOptions { viewport })?;
+
+        let mut events = Crossterm {
+            inline: self.inline.is_some(),
+        };
+        // Restore the terminal even if the loop fails, so the error is readable
+        let selection = self.run_with(&mut terminal, &mut events).await;
+        suspend(&mut terminal, self.inline.is_some())?;
+
+        selection
+    }
+
+    /// Act on the best match for the query without showing anything, once indexing is done, as
+    /// Enter would on it. Fails if nothing matches.
+    pub async fn run_first(&mut self) -> Result<Option<Vec<Ref>>> {
+        while self.indexer.is_some() {
+            self.receive_refs();
+            tokio::time::sleep(INDEXING_POLL).await;
+        }
+        // Straight to the match rather than its group or section
+        self.grouped = false;
+        self.by_kind = false;
+        if self.search_results.is_empty() {
+            return Err(Error::NoMatch(self.input.clone()));
+        }
+        self.search_result_state.select(Some(0));
+        let selection = self.act(self.enter_action, true).await?;
+        if let Some(pending) = &self.pending {
+            let selection = pending.selection.clone();
+            self.wait_pending().await?;
+            return Ok(Some(selection));
+        }
+        Ok(selection)
+    }
+
+    /// The loop behind [`App::run`], drawing to `terminal` and reacting to `events` until the
+    /// user picks something or cancels. Setting the terminal up and restoring it is left to the
+    /// caller.
+    pub async fn run_with<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        events: &mut impl EventSource<B>,
+    ) -> Result<Option<Vec<Ref>>> {
+        let mut previewed = None;
+        let selection = loop {
+            self.receive_refs();
+            if let Some(selection) = self.poll_pending().await? {
+                break Some(selection);
+            }
+            // Keep the spinner turning while indexing or waiting on the editor
+            if self.indexer.is_some() || self.pending.is_some() {
+                self.tick = self.tick.wrapping_add(1);
+                self.dirty = true;
+            }
+            if self.dirty {
+                let selected = self.get_selected_ref();
+                if selected != previewed {
+                    self.preview_scroll = 0;
+                    previewed = selected;
+                }
+                terminal.draw(|f| tui::ui(f, self))?;
+                self.dirty = false;
+            }
+            // Wake up regularly while indexing so newly indexed refs show up, and while waiting
+            // on the editor to notice when it is done
+            let timeout = if self.indexer.is_some() || self.pending.is_some() {
+                INDEXING_POLL
+            } else {
+                IDLE_POLL
+            };
+            let Some(event) = events.next_event(timeout)? else {
+                continue;
+            };
+            if let Event::Resize(..) = event {
+                self.dirty = true;
+            }
+            if let Event::Key(key) = event {
+                if key.kind == event::KeyEventKind::Release {
+                    continue;
+                }
+                // Nearly every key changes something on screen, if only by clearing the status
+                self.dirty = true;
+                self.status = None;
+
+                // Act on the selection but keep browsing. Most terminals only report Ctrl-Enter
+                // with the kitty keyboard protocol, Alt-Enter works everywhere.
+                if key.code == KeyCode::Enter
+                    && (key.modifiers == KeyModifiers::CONTROL
+                        || key.modifiers == KeyModifiers::ALT)
+                {
+                    if self.act(self.alt_enter_action, false).await?.is_some() {
+                        self.marked.clear();
+                    }
+                    continue;
+                }
+
+                if key.modifiers == KeyModifiers::CONTROL {
+                    // Allow Ctrl-j/k to move up and down selection
+                    if let KeyCode::Char(ch) = key.code {
+                        match ch {
+                            'j' => self.move_down(),
+                            'k' => self.move_up(),
+                            'l' => self.focus = self.focus.next(),
+                            // Legacy terminals send Ctrl-h as Backspace, Ctrl-l alone still
+                            // reaches every pane
+                            'h' => self.focus = self.focus.previous(),
+                            // Toggle between the whole index and the exported API surface
+                            'v' => {
+                                self.public_only = !self.public_only;
+                                self.recompute_results();
+                            }
+                            'w' => {
+                                delete_word(&mut self.input, &mut self.cursor);
+                                self.recompute_results();
+                            }
+                            'u' => {
+                                self.input.clear();
+                                self.cursor = 0;
+                                self.recompute_results();
+                            }
+                            'g' => {
+                                let selected = self.get_selected_ref();
+                                self.grouped = !self.grouped;
+                                self.reselect(selected);
+                            }
+                            // Open the file in the default app, which may be a terminal one
+                            'o' => {
+                                if let Some(r) = self.get_selected_ref() {
+                                    events.suspend(terminal)?;
+                                    let opened = open::open_ref(&r);
+                                    events.resume(terminal)?;
+                                    // Report a failed launch in the status line and keep the TUI running
+                                    if let Err(err) = opened {
+                                        self.status = Some(match err {
+                                            Error::Open(file, err) => {
+                                                format!("failed to open {}: {err}", file.display())
+                                            }
+                                            err => err.to_string(),
+                                        });
+                                    }
+                                }
+                            }
+                            'r' => self.reload(),
+                            'q' => self.send_quickfix().await,
+                            'b' => self.send_scratch().await,
+                            'y' => {
+                                if let Some(r) = self.get_selected_ref() {
+                                    self.copy("signature", r.sig);
+                                }
+                            }
+                            't' => self.highlight_query = !self.highlight_query,
+                            'f' => self.show_paths = !self.show_paths,
+                            'n' => self.show_line_counts = !self.show_line_counts,
+                            'd' => {
+                                self.search_docs = !self.search_docs;
+                                self.recompute_results();
+                            }
+                            's' => {
+                                // Keep the selection on the same ref while the order changes
+                                let selected = self.get_selected_ref();
+                                self.sort = self.sort.next();
+                                self.reselect(selected);
+                            }
+                            _ => {}
+                        }
+                    }
+                } else if key.modifiers == KeyModifiers::ALT {
+                    // Allow Alt-Up/Down to raise and lower the minimum score
+                    match key.code {
+                        // Copy the whole item rather than just its signature
+                        KeyCode::Char('y') => {
+                            if let Some(r) = self.get_selected_ref() {
+                                match r.source() {
+                                    Ok(source) => self.copy("item", source),
+                                    Err(err) => {
+                                        self.status = Some(format!("failed to read item: {err}"))
+                                    }
+                                }
+                            }
+                        }
+                        KeyCode::Char('w') => self.preview_wrap = !self.preview_wrap,
+                        KeyCode::Char('n') => self.select_same_name(),
+                        KeyCode::Char('o') => self.toggle_outline(),
+                        KeyCode::Char('k') => {
+                            let selected = self.get_selected_ref();
+                            self.by_kind = !self.by_kind;
+                            self.reselect(selected);
+                            self.select_first();
+                        }
+                        KeyCode::Char('l') => {
+                            if let Some(r) = self.get_selected_ref() {
+                                let link =
+                                    markdown_link(&r, &self.root, self.link_template.as_deref());
+                                self.copy("link", link);
+                            }
+                        }
+                        KeyCode::Up => {
+                            self.min_score += MIN_SCORE_STEP;
+                            self.recompute_results();
+                        }
+                        KeyCode::Down => {
+                            self.min_score -= MIN_SCORE_STEP;
+                            self.recompute_results();
+                        }
+                        _ => {}
+                    }
+                } else if key.modifiers == KeyModifiers::SHIFT {
+                    // Allow Shift+Tab to mark and move up selection
+                    match key.code {
+                        KeyCode::BackTab => {
+                            self.toggle_mark();
+                            self.select_previous();
+                        }
+                        KeyCode::Char(ch) => self.insert_char(ch),
+                        _ => {}
+                    }
+                } else if key.modifiers == KeyModifiers::NONE {
+                    // All other normal keybinds
+                    match key.code {
+                        KeyCode::Esc if self.show_help => self.show_help = false,
+                        KeyCode::Esc if self.pending.is_some() => self.cancel_pending(),
+                        KeyCode::Esc => break None,
+                        KeyCode::F(1) => self.show_help = !self.show_help,
+                        KeyCode::Char(ch) => self.insert_char(ch),
+                        KeyCode::Up => self.move_up(),
+                        KeyCode::BackTab => {
+                            self.toggle_mark();
+                            self.select_previous();
+                        }
+                        KeyCode::Down => self.move_down(),
+                        KeyCode::Tab => {
+                            self.toggle_mark();
+                            self.select_next();
+                        }
+                        KeyCode::Backspace => {
+                            if let Some(ch) = self.input[..self.cursor].chars().next_back() {
+                                self.cursor -= ch.len_utf8();
+                                self.input.remove(self.cursor);
+                                self.recompute_results();
+                            }
+                        }
+                        KeyCode::Delete if self.cursor < self.input.len() => {
+                            self.input.remove(self.cursor);
+                            self.recompute_results();
+                        }
+                        // Expand and collapse a selected group, otherwise move the cursor
+                        KeyCode::Left if self.set_expanded(false) => {}
+                        KeyCode::Right if self.set_expanded(true) => {}
+                        KeyCode::Left => {
+                            if let Some(ch) = self.input[..self.cursor].chars().next_back() {
+                                self.cursor -= ch.len_utf8();
+                            }
+                        }
+                        KeyCode::Right => {
+                            if let Some(ch) = self.input[self.cursor..].chars().next() {
+                                self.cursor += ch.len_utf8();
+                            }
+                        }
+                        KeyCode::Home => self.cursor = 0,
+                        KeyCode::End => self.cursor = self.input.len(),
+                        KeyCode::Enter => {
+                            if let Some(Row::Group { expanded, .. }) = self.get_selected_row() {
+                                self.set_expanded(!expanded);
+                                continue;
+                            }
+                            // Continue if nothing is selected
+                            let action = self.enter_action;
+                            if let Some(selection) = self.act(action, true).await? {
+                                if action.closes() {
+                                    break Some(selection);
+                                }
+                                self.marked.clear();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        };
+        Ok(selection)
+    }
+
+    /// Scroll the preview up when it has focus, otherwise select the previous result.
+    fn move_up(&mut self) {
+        match self.focus {
+            Focus::Preview => self.preview_scroll = self.preview_scroll.saturating_sub(1),
+            Focus::Input | Focus::Results => self.select_previous(),
+        }
+    }
+
+    /// Scroll the preview down when it has focus, otherwise select the next result.
+    fn move_down(&mut self) {
+        match self.focus {
+            Focus::Preview => self.preview_scroll = self.preview_scroll.saturating_add(1),
+            Focus::Input | Focus::Results => self.select_next(),
+        }
+    }
+
+    /// Select the next row the selection can rest on, skipping section headers.
+    fn select_next(&mut self) {
+        if !self.by_kind {
+            return self.search_result_state.select_next();
+        }
+        let rows = self.rows();
+        let from = self.search_result_state.selected().map_or(0, |i| i + 1);
+        if let Some(i) = (from..rows.len()).find(|&i| rows[i].is_selectable()) {
+            self.search_result_state.select(Some(i));
+        }
+    }
+
+    /// Select the previous row the selection can rest on, skipping section headers.
+    fn select_previous(&mut self) {
+        if !self.by_kind {
+            return self.search_result_state.select_previous();
+        }
+        let rows = self.rows();
+        let to = self
+            .search_result_state
+            .selected()
+            .unwrap_or(0)
+            .min(rows.len());
+        if let Some(i) = (0..to).rev().find(|&i| rows[i].is_selectable()) {
+            self.search_result_state.select(Some(i));
+        }
+    }
+
+    pub fn get_selected_row(&self) -> Option<Row> {
         let i = self.search_result_state.selected()?;
-        self.search_results
-            .clone()
-            .into_sorted_iter()
-            .nth(i)
-            .map(|x| x.0)
+        self.rows().into_iter().nth(i)
+    }
+
+    /// The other indexed refs with the same name as `r`, in file order, e.g. every `new`.
+    pub fn same_name(&self, r: &Ref) -> Vec<&Ref> {
+        let mut others: Vec<&Ref> = self
+            .refs
+            .iter()
+            .filter(|other| other.name == r.name && *other != r)
+            .collect();
+        others.sort_by_key(|other| (&other.file, other.line, other.column));
+        others
+    }
+
+    /// Select the next ref with the same name as the selected one, in file order and wrapping
+    /// around. Its group is expanded, but it can't be selected if the query doesn't match it.
+    fn select_same_name(&mut self) {
+        let Some(selected) = self.get_selected_ref() else {
+            return;
+        };
+        let location = |r: &Ref| (r.file.clone(), r.line, r.column);
+        let others = self.same_name(&selected);
+        let Some(next) = others
+            .iter()
+            .find(|other| location(other) > location(&selected))
+            .or(others.first())
+            .map(|other| (*other).clone())
+        else {
+            self.status = Some(format!("no other definitions named `{}`", selected.name));
+            return;
+        };
+        if self.grouped {
+            self.expanded.insert(next.name.clone());
+        }
+        if !self.rows().iter().any(|row| row.target() == Some(&next)) {
+            self.status = Some(format!("{} doesn't match the query", next.path()));
+            return;
+        }
+        self.reselect(Some(next));
+    }
+
+    /// Narrow the results down to the symbols of the selected ref's file, or go back to the
+    /// whole index. The selection stays on the same ref either way.
+    fn toggle_outline(&mut self) {
+        let selected = self.get_selected_ref();
+        if self.outline.take().is_none() {
+            let Some(r) = &selected else {
+                self.status = Some("select a symbol to outline its file".into());
+                return;
+            };
+            self.outline = Some(r.file.clone());
+        }
+        self.recompute_results();
+        self.reselect(selected);
+    }
+
+    /// The selected ref, `None` while a group header is selected.
+    pub fn get_selected_ref(&self) -> Option<Ref> {
+        self.get_selected_row()?.target().cloned()
+    }
+
+    /// The lines of the results list. When grouping, refs sharing a name are collapsed into a
+    /// group ranked by its best match, names only occurring once stay a plain row.
+    pub fn rows(&self) -> Vec<Row> {
+        let ranked = self.ranked_results();
+        if !self.by_kind {
+            return self.group_rows(ranked);
+        }
+        // Sections in a fixed order, each keeping the ranking within
+        let mut sections: Vec<(RefKind, Vec<Ref>)> = Vec::new();
+        for r in ranked {
+            match sections.iter_mut().find(|(kind, _)| *kind == r.kind) {
+                Some((_, refs)) => refs.push(r),
+                None => sections.push((r.kind, vec![r])),
+            }
+        }
+        sections.sort_by_key(|(kind, _)| *kind);
+        let mut rows = Vec::new();
+        for (kind, refs) in sections {
+            rows.push(Row::Section {
+                kind,
+                count: refs.len(),
+            });
+            rows.extend(self.group_rows(refs));
+        }
+        rows
+    }
+
+    /// Rows for `ranked`, with refs sharing a name collapsed into groups when grouping.
+    fn group_rows(&self, ranked: Vec<Ref>) -> Vec<Row> {
+        if !self.grouped {
+            return ranked.into_iter().map(Row::Ref).collect();
+        }
+
+        let mut groups: Vec<Vec<Ref>> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for r in ranked {
+            match index.get(&r.name) {
+                Some(&i) => groups[i].push(r),
+                None => {
+                    index.insert(r.name.to_owned(), groups.len());
+                    groups.push(vec![r]);
+                }
+            }
+        }
+
+        let mut rows = Vec::new();
+        for mut group in groups {
+            if group.len() == 1 {
+                rows.extend(group.pop().map(Row::Ref));
+                continue;
+            }
+            let name = group[0].name.to_owned();
+            let expanded = self.expanded.contains(&name);
+            rows.push(Row::Group {
+                name,
+                count: group.len(),
+                expanded,
+            });
+            if expanded {
+                rows.extend(group.into_iter().map(Row::Member));
+            }
+        }
+        rows
+    }
+
+    /// How many rows [`App::rows`] has, without ranking the results when each is a row.
+    fn row_count(&self) -> usize {
+        if self.grouped || self.by_kind {
+            return self.rows().len();
+        }
+        match self.max_results {
+            0 => self.search_results.len(),
+            max => self.search_results.len().min(max),
+        }
+    }
+
+    /// The search results best match first, refs with equal scores ordered by `sort`. Only the
+    /// best `max_results` are kept, only those have to be fully sorted.
+    pub fn ranked_results(&self) -> Vec<Ref> {
+        let mut results: Vec<(&Ref, &Priority)> = self.search_results.iter().collect();
+        let order = |(a, a_prio): &(&Ref, &Priority), (b, b_prio): &(&Ref, &Priority)| {
+            b_prio
+                .cmp(a_prio)
+                .then_with(|| self.sort.compare(a, b, &self.mtimes))
+        };
+        if self.is_truncated() {
+            results.select_nth_unstable_by(self.max_results - 1, order);
+            results.truncate(self.max_results);
+        }
+        results.sort_by(order);
+        results.into_iter().map(|(r, _)| r.to_owned()).collect()
+    }
+
+    /// Whether there are more matches than are listed.
+    pub fn is_truncated(&self) -> bool {
+        self.max_results > 0 && self.search_results.len() > self.max_results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Deref, DerefMut};
+
+    use super::*;
+    use crate::fixture::{Fixture, fixture};
+
+    fn r(name: &str, kind: RefKind, file: &str, line: usize, end_line: usize) -> Ref {
+        Ref {
+            name: name.into(),
+            sig: format!("fn {name}()"),
+            kind,
+            file: PathBuf::from(file),
+            line,
+            end_line,
+            ..Default::default()
+        }
+    }
+
+    /// The names of `refs` in the order `sort` puts them.
+    fn sorted(sort: SortOrder, mut refs: Vec<Ref>) -> Vec<String> {
+        let mtimes = Mtimes::default();
+        refs.sort_by(|a, b| sort.compare(a, b, &mtimes));
+        refs.into_iter().map(|r| r.name).collect()
+    }
+
+    fn mixed() -> Vec<Ref> {
+        vec![
+            r("beta", RefKind::Struct, "b.rs", 10, 40),
+            r("alpha", RefKind::Fn, "b.rs", 2, 3),
+            r("gamma", RefKind::Fn, "a.rs", 7, 7),
+            r("alpha", RefKind::Enum, "a.rs", 20, 30),
+        ]
+    }
+
+    #[test]
+    fn sort_by_name_then_location() {
+        assert_eq!(
+            sorted(SortOrder::Name, mixed()),
+            ["alpha", "alpha", "beta", "gamma"]
+        );
+        let refs = mixed();
+        let mtimes = Mtimes::default();
+        // The `alpha` in `a.rs` comes first
+        assert_eq!(
+            SortOrder::Name.compare(&refs[3], &refs[1], &mtimes),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn sort_by_file_then_position() {
+        assert_eq!(
+            sorted(SortOrder::File, mixed()),
+            ["gamma", "alpha", "alpha", "beta"]
+        );
+    }
+
+    #[test]
+    fn sort_by_kind_then_name() {
+        // Fns before enums before structs, in the order the kinds are declared
+        assert_eq!(
+            sorted(SortOrder::Kind, mixed()),
+            ["alpha", "gamma", "alpha", "beta"]
+        );
+    }
+
+    #[test]
+    fn sort_by_lines_longest_first() {
+        assert_eq!(
+            sorted(SortOrder::Lines, mixed()),
+            ["beta", "alpha", "alpha", "gamma"]
+        );
+    }
+
+    #[test]
+    fn sort_orders_cycle() {
+        let mut sort = SortOrder::default();
+        let mut seen = Vec::new();
+        for _ in SortOrder::value_variants() {
+            seen.push(sort);
+            sort = sort.next();
+        }
+        assert_eq!(sort, SortOrder::default());
+        assert_eq!(seen, SortOrder::value_variants());
+    }
+
+    /// An app over a fixture directory, done indexing it.
+    /// An app done indexing a fixture, which lives as long as the app does.
+    struct TestApp {
+        app: App,
+        _dir: Fixture,
+    }
+
+    impl Deref for TestApp {
+        type Target = App;
+
+        fn deref(&self) -> &App {
+            &self.app
+        }
+    }
+
+    impl DerefMut for TestApp {
+        fn deref_mut(&mut self) -> &mut App {
+            &mut self.app
+        }
+    }
+
+    /// The app `builder` builds for `dir`, once it is done indexing.
+    fn indexed(builder: AppBuilder, dir: Fixture) -> TestApp {
+        let mut app = builder.root(&*dir).build().unwrap();
+        while app.indexer.is_some() {
+            app.receive_refs();
+            thread::sleep(INDEXING_POLL);
+        }
+        TestApp { app, _dir: dir }
+    }
+
+    fn app(files: &[(&str, &str)]) -> TestApp {
+        indexed(App::builder(), fixture(files))
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(event::KeyEvent::new(code, modifiers))
+    }
+
+    fn typed(text: &str) -> Vec<Event> {
+        text.chars()
+            .map(|ch| key(KeyCode::Char(ch), KeyModifiers::NONE))
+            .collect()
+    }
+
+    /// Run `app` on `events` against a test terminal, returning what it picked. The app is
+    /// cancelled with Esc once the events run out.
+    async fn drive(app: &mut App, events: Vec<Event>) -> Option<Vec<Ref>> {
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        app.run_with(&mut terminal, &mut events.into_iter())
+            .await
+            .unwrap()
+    }
+
+    fn result_names(app: &App) -> Vec<String> {
+        app.ranked_results().into_iter().map(|r| r.name).collect()
+    }
+
+    const PARSERS: &[(&str, &str)] = &[(
+        "lib.rs",
+        "fn parse_file() {} fn parse_header() {} fn print() {} fn pad() {} fn other() {}",
+    )];
+
+    #[tokio::test]
+    async fn results_dont_depend_on_how_the_query_was_typed() {
+        let mut typed_forward = app(PARSERS);
+        drive(&mut typed_forward, typed("pa")).await;
+
+        let mut deleted_back = app(PARSERS);
+        let mut events = typed("parx");
+        events.extend(vec![key(KeyCode::Backspace, KeyModifiers::NONE); 2]);
+        drive(&mut deleted_back, events).await;
+
+        assert_eq!(deleted_back.input, "pa");
+        assert_eq!(result_names(&deleted_back), result_names(&typed_forward));
+        assert!(result_names(&typed_forward).contains(&"parse_file".to_owned()));
+        assert!(!result_names(&typed_forward).contains(&"other".to_owned()));
+    }
+
+    /// `input` after deleting the word before `cursor`, with `|` marking the cursor.
+    fn deleted_word(input: &str, cursor: usize) -> String {
+        let (mut input, mut cursor) = (input.to_owned(), cursor);
+        delete_word(&mut input, &mut cursor);
+        input.insert(cursor, '|');
+        input
+    }
+
+    #[test]
+    fn ctrl_w_deletes_up_to_underscores_and_spaces() {
+        let header = "parse_file_header";
+        assert_eq!(deleted_word(header, header.len()), "parse_file_|");
+        assert_eq!(deleted_word("parse_file_", 11), "parse_|");
+        assert_eq!(deleted_word("parse_", 6), "|");
+        assert_eq!(deleted_word("fn parse  ", 10), "fn |");
+        assert_eq!(deleted_word("@fn parse", 9), "@fn |");
+        assert_eq!(deleted_word("", 0), "|");
+    }
+
+    #[test]
+    fn ctrl_w_keeps_what_follows_the_cursor() {
+        assert_eq!(deleted_word("parse_file_header", 10), "parse_|_header");
+        assert_eq!(deleted_word("größe_ändern", "größe_".len()), "|ändern");
+    }
+
+    #[tokio::test]
+    async fn ctrl_u_clears_the_query() {
+        let mut app = app(PARSERS);
+        let mut events = typed("other");
+        events.push(key(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        drive(&mut app, events).await;
+        assert_eq!((app.input.as_str(), app.cursor), ("", 0));
+        assert_eq!(app.ranked_results().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn selecting_a_ref_of_a_deleted_file_drops_its_refs() {
+        let mut app = app(&[("gone.rs", "fn gone() {}"), ("kept.rs", "fn kept() {}")]);
+        fs::remove_file(app.root().join("gone.rs")).unwrap();
+        let mut events = typed("gone");
+        events.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(drive(&mut app, events).await, None);
+        assert!(app.refs.iter().all(|r| r.name != "gone"));
+        assert!(app.ranked_results().is_empty());
+        assert_eq!(app.search_result_state.selected(), None);
+        assert!(app.refs.iter().any(|r| r.name == "kept"));
+    }
+
+    #[test]
+    fn each_matcher_scores_a_known_query() {
+        for matcher in Matcher::value_variants() {
+            let built = matcher.build();
+            let score = built.fuzzy_match("fn parse_file_header()", "pfh");
+            assert!(score.is_some_and(|score| score > 0), "{matcher}");
+            let (_, indices) = built.fuzzy_indices("fn parse()", "prs").unwrap();
+            assert_eq!(indices, [3, 5, 6], "{matcher}");
+            assert_eq!(built.fuzzy_match("fn parse()", "xyz"), None, "{matcher}");
+        }
+    }
+
+    #[test]
+    fn the_configured_matcher_is_used() {
+        let dir = fixture(PARSERS);
+        let app = App::builder()
+            .root(&*dir)
+            .matcher(Matcher::Skim)
+            .build()
+            .unwrap();
+        let skim = SkimMatcherV2::default();
+        assert_eq!(
+            app.matcher.fuzzy_match("fn parse_file()", "pf"),
+            skim.fuzzy_match("fn parse_file()", "pf")
+        );
+    }
+
+    #[test]
+    fn builder_indexes_its_root_with_its_options() {
+        let dir = fixture(&[
+            ("src/lib.rs", "pub fn public() {} fn private() {}"),
+            ("src/notes.txt", "fn not_rust() {}"),
+        ]);
+        let opts = IndexOptions {
+            kinds: vec![RefKind::Fn],
+            ..Default::default()
+        };
+        let mut app = App::builder()
+            .root(&*dir)
+            .options(opts)
+            .query("pub")
+            .build()
+            .unwrap();
+        while app.indexer.is_some() {
+            app.receive_refs();
+            thread::sleep(INDEXING_POLL);
+        }
+        assert_eq!(app.root(), dir.canonicalize().unwrap());
+        assert_eq!((app.input.as_str(), app.cursor), ("pub", 3));
+        let mut names: Vec<&str> = app.refs.iter().map(|r| r.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["private", "public"]);
+        // The initial query is applied to what comes in
+        assert_eq!(result_names(&app)[0], "public");
+    }
+
+    #[test]
+    fn visibility_doesnt_take_part_in_matching() {
+        let mut app = app(&[("lib.rs", "pub fn apply_rules() {} fn publish() {}")]);
+        app.input = "pub".into();
+        app.recompute_results();
+        assert_eq!(result_names(&app), ["publish"]);
+        // Matching the signatures as displayed, `pub` matches both and the visibility wins
+        let matcher = Matcher::Clangd.build();
+        let raw = matcher.fuzzy_match("pub fn apply_rules()", "pub");
+        let normalized = matcher.fuzzy_match("fn publish()", "pub");
+        assert!(raw.is_some() && raw >= normalized);
+    }
+
+    #[test]
+    fn alias_queries_find_the_aliased_item() {
+        let mut app = app(&[(
+            "lib.rs",
+            r#"#[doc(alias = "malloc")] pub fn allocate() {} pub fn free() {}"#,
+        )]);
+        app.input = "malloc".into();
+        app.recompute_results();
+        assert_eq!(result_names(&app), ["allocate"]);
+        // Still shown by its own signature
+        assert_eq!(app.ranked_results()[0].display_sig(), "pub fn allocate()");
+    }
+
+    const DOCUMENTED: &[(&str, &str)] = &[(
+        "lib.rs",
+        "/// Reads the configuration from disk
+        pub fn load() {}
+        /// Writes the configuration back
+        pub fn save() {}
+        pub fn configuration() {}",
+    )];
+
+    #[test]
+    fn doc_only_queries_match_in_doc_search_mode() {
+        let mut app = app(DOCUMENTED);
+        app.input = "from disk".into();
+        app.recompute_results();
+        assert!(result_names(&app).is_empty());
+
+        app.search_docs = true;
+        app.recompute_results();
+        assert_eq!(result_names(&app), ["load"]);
+        assert!(app.matched_docs(&Query::parse(&app.input), &app.ranked_results()[0]));
+    }
+
+    #[test]
+    fn name_matches_outrank_doc_matches() {
+        let mut app = app(DOCUMENTED);
+        app.search_docs = true;
+        app.input = "configuration".into();
+        app.recompute_results();
+        let results = app.ranked_results();
+        assert_eq!(results[0].name, "configuration");
+        let query = Query::parse(&app.input);
+        assert!(!app.matched_docs(&query, &results[0]));
+        assert!(results[1..].iter().all(|r| app.matched_docs(&query, r)));
+    }
+
+    /// The results and their ranks, in order.
+    fn ranked(app: &App) -> Vec<(String, Priority)> {
+        let mut ranked: Vec<(String, Priority)> = app
+            .search_results
+            .iter()
+            .map(|(r, prio)| (r.path(), *prio))
+            .collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked
+    }
+
+    #[test]
+    fn narrowed_results_equal_a_full_recompute() {
+        let files = &[(
+            "lib.rs",
+            "pub fn parse_file() {} fn parse_header() {} struct Parser; fn print() {}
+            fn apply() {} mod parsing { fn parse() {} }",
+        )];
+        let mut narrowed = app(files);
+        for ch in "pars".chars() {
+            narrowed.insert_char(ch);
+        }
+        assert!(narrowed.narrowed.is_some());
+
+        for input in ["pars", "@fn pars", "'parse_", "pa$"] {
+            let mut full = app(files);
+            full.input = input.into();
+            full.recompute_results();
+
+            let mut typed = app(files);
+            for ch in input.chars() {
+                typed.insert_char(ch);
+            }
+            assert_eq!(ranked(&typed), ranked(&full), "{input}");
+        }
+        assert_eq!(ranked(&narrowed).len(), 5);
+    }
+
+    const MIXED_KINDS: &[(&str, &str)] = &[(
+        "lib.rs",
+        "use std::fmt; const C: u8 = 0; enum E {} fn f() {} struct S;",
+    )];
+
+    fn all_kinds() -> IndexOptions {
+        IndexOptions {
+            kinds: RefKind::ALL.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// An app over `MIXED_KINDS`, indexing every kind.
+    fn mixed_kinds() -> TestApp {
+        indexed(App::builder().options(all_kinds()), fixture(MIXED_KINDS))
+    }
+
+    #[test]
+    fn default_weights_demote_imports() {
+        let mut app = mixed_kinds();
+        app.recompute_results();
+        let kinds: Vec<RefKind> = app.ranked_results().iter().map(|r| r.kind).collect();
+        // Boosted fns and structs by name, then the rest by name, imports last
+        assert_eq!(
+            kinds,
+            [
+                RefKind::Struct,
+                RefKind::Fn,
+                RefKind::Const,
+                RefKind::Enum,
+                RefKind::Use
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_weights_reorder_kinds() {
+        let mut app = mixed_kinds();
+        app.kind_weights = HashMap::from([(RefKind::Enum, 20), (RefKind::Const, -50)]);
+        app.recompute_results();
+        let kinds: Vec<RefKind> = app.ranked_results().iter().map(|r| r.kind).collect();
+        assert_eq!(kinds.first(), Some(&RefKind::Enum));
+        assert_eq!(kinds.last(), Some(&RefKind::Const));
+    }
+
+    /// What `app` draws on a test terminal, row by row.
+    fn screen(app: &mut App) -> String {
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| crate::tui::ui(frame, app)).unwrap();
+        drawn(&terminal)
+    }
+
+    /// What was drawn on `terminal` last, row by row.
+    fn drawn(terminal: &Terminal<ratatui::backend::TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[tokio::test]
+    async fn line_counts_are_shown_on_request() {
+        let mut app = app(&[(
+            "lib.rs",
+            "fn long() {\n    let a = 1;\n    let b = 2;\n}\nfn short() {}\n",
+        )]);
+        assert!(!screen(&mut app).contains("lines)"));
+        drive(
+            &mut app,
+            vec![key(KeyCode::Char('n'), KeyModifiers::CONTROL)],
+        )
+        .await;
+        let screen = screen(&mut app);
+        assert!(screen.contains("fn long()  (4 lines)"), "{screen}");
+        assert!(screen.contains("fn short()  (1 line)"), "{screen}");
+    }
+
+    #[test]
+    fn markdown_links_fill_in_the_template() {
+        let mut parse = r("parse", RefKind::Fn, "/repo/src/index.rs", 12, 20);
+        parse.module_path = vec!["index".into()];
+        let template = "https://github.com/owner/repo/blob/main/{file}#L{line}";
+        assert_eq!(
+            markdown_link(&parse, Path::new("/repo"), Some(template)),
+            "[crate::index::parse](https://github.com/owner/repo/blob/main/src/index.rs#L12)"
+        );
+        // Every placeholder is replaced, not just the first
+        assert_eq!(
+            markdown_link(&parse, Path::new("/repo"), Some("{file}:{line} {file}")),
+            "[crate::index::parse](src/index.rs:12 src/index.rs)"
+        );
+    }
+
+    #[test]
+    fn markdown_links_without_a_template_are_locations() {
+        let parse = r("parse", RefKind::Fn, "/repo/src/index.rs", 12, 20);
+        assert_eq!(
+            markdown_link(&parse, Path::new("/repo"), None),
+            "src/index.rs:12"
+        );
+    }
+
+    #[tokio::test]
+    async fn kind_filters_narrow_the_results() {
+        let mut app = app(&[(
+            "lib.rs",
+            "fn parse() {} struct Parser; pub fn print() {} struct Printer;",
+        )]);
+        drive(&mut app, typed("@struct parse")).await;
+        assert_eq!(result_names(&app), ["Parser"]);
+        drive(
+            &mut app,
+            vec![key(KeyCode::Char('u'), KeyModifiers::CONTROL)],
+        )
+        .await;
+        drive(&mut app, typed("#pub pr")).await;
+        assert_eq!(result_names(&app), ["print"]);
+    }
+
+    /// Terminal reads as they may come, handled like those of the real terminal. `Ok(None)`
+    /// waits a moment as if no key was pressed. Once they run out Esc is pressed.
+    struct Reads(std::vec::IntoIter<io::Result<Option<Event>>>);
+
+    impl<B: Backend> EventSource<B> for Reads {
+        fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+            match self.0.next() {
+                Some(Ok(None)) => {
+                    thread::sleep(timeout.min(INDEXING_POLL));
+                    Ok(None)
+                }
+                Some(read) => retry_interrupted(read),
+                None => Ok(Some(Event::Key(KeyCode::Esc.into()))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn interrupted_reads_dont_end_the_app() {
+        let mut app = app(PARSERS);
+        let interrupted = || Err(io::Error::from(io::ErrorKind::Interrupted));
+        let mut events = Reads(
+            vec![
+                Ok(Some(key(KeyCode::Char('p'), KeyModifiers::NONE))),
+                interrupted(),
+                Ok(Some(key(KeyCode::Char('r'), KeyModifiers::NONE))),
+                interrupted(),
+            ]
+            .into_iter(),
+        );
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        assert_eq!(
+            app.run_with(&mut terminal, &mut events).await.unwrap(),
+            None
+        );
+        assert_eq!(app.input, "pr");
+    }
+
+    #[tokio::test]
+    async fn other_read_errors_end_the_app() {
+        let mut app = app(PARSERS);
+        let mut events = Reads(vec![Err(io::Error::other("gone"))].into_iter());
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        assert!(app.run_with(&mut terminal, &mut events).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn the_best_match_is_selected_once_there_are_results() {
+        let mut app = indexed(App::builder().query("print"), fixture(PARSERS));
+        assert_eq!(app.search_result_state.selected(), Some(0));
+        assert_eq!(app.get_selected_ref().unwrap().name, "print");
+
+        // No selection without results, and the first again once there are some
+        drive(&mut app, typed("zzz")).await;
+        assert!(app.get_selected_ref().is_none());
+        drive(
+            &mut app,
+            vec![key(KeyCode::Char('u'), KeyModifiers::CONTROL)],
+        )
+        .await;
+        assert_eq!(app.search_result_state.selected(), Some(0));
+    }
+
+    /// Events that leave the app some idle turns afterwards, to finish what they started.
+    fn then_idle(events: Vec<Event>) -> Reads {
+        let idle = (0..40).map(|_| Ok(None));
+        Reads(
+            events
+                .into_iter()
+                .map(|event| Ok(Some(event)))
+                .chain(idle)
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    }
+
+    // The callback runs on the runtime while the loop waits for input
+    #[tokio::test(flavor = "multi_thread")]
+    async fn enter_calls_back_with_the_selection() {
+        let mut app = app(PARSERS);
+        let called = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        app.select_callback = Some(Box::new({
+            let called = called.clone();
+            move |selection: Vec<Ref>| {
+                called
+                    .lock()
+                    .unwrap()
+                    .extend(selection.into_iter().map(|r| r.name));
+                async { Ok(()) }
+            }
+        }));
+        let mut events = typed("parse");
+        events.push(key(KeyCode::Down, KeyModifiers::NONE));
+        events.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        let picked = app
+            .run_with(&mut terminal, &mut then_idle(events))
+            .await
+            .unwrap()
+            .unwrap();
+        let second = result_names(&app)[1].clone();
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].name, second);
+        assert_eq!(*called.lock().unwrap(), [second]);
+    }
+
+    #[test]
+    fn sort_by_modified_puts_recent_files_first() {
+        let dir = fixture(&[("old.rs", ""), ("new.rs", ""), ("newest.rs", "")]);
+        let touch = |file: &str, secs_ago: u64| {
+            let mtime = SystemTime::now() - Duration::from_secs(secs_ago);
+            fs::File::options()
+                .write(true)
+                .open(dir.join(file))
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        };
+        touch("old.rs", 3000);
+        touch("new.rs", 200);
+        touch("newest.rs", 10);
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let refs = vec![
+            r("gone", RefKind::Fn, &file("gone.rs"), 1, 1),
+            r("old", RefKind::Fn, &file("old.rs"), 1, 1),
+            r("second", RefKind::Fn, &file("new.rs"), 9, 9),
+            r("first", RefKind::Fn, &file("new.rs"), 2, 2),
+            r("newest", RefKind::Fn, &file("newest.rs"), 1, 1),
+        ];
+        // Within a file by position, files that can't be read last
+        assert_eq!(
+            sorted(SortOrder::Modified, refs),
+            ["newest", "first", "second", "old", "gone"]
+        );
+
+        // Cached until cleared
+        let mut mtimes = Mtimes::default();
+        let before = mtimes.get(&dir.join("old.rs"));
+        touch("old.rs", 0);
+        assert_eq!(mtimes.get(&dir.join("old.rs")), before);
+        mtimes.clear();
+        assert_ne!(mtimes.get(&dir.join("old.rs")), before);
+    }
+
+    const NEWS: &[(&str, &str)] = &[
+        ("a.rs", "fn new() {} fn other() {}"),
+        ("b.rs", "struct S; impl S { fn new() -> Self { S } }"),
+        ("c.rs", "fn new() {}"),
+    ];
+
+    fn named<'a>(app: &'a App, name: &str, file: &str) -> &'a Ref {
+        app.refs
+            .iter()
+            .find(|r| r.name == name && r.file.ends_with(file))
+            .unwrap()
+    }
+
+    #[test]
+    fn refs_with_the_same_name_are_counted() {
+        let app = app(NEWS);
+        let others: Vec<_> = app
+            .same_name(named(&app, "new", "b.rs"))
+            .into_iter()
+            .map(|r| r.file.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(others, ["a.rs", "c.rs"]);
+        assert!(app.same_name(named(&app, "other", "a.rs")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn alt_n_cycles_through_the_same_name() {
+        let mut app = app(NEWS);
+        drive(&mut app, typed("^fn new")).await;
+        let file = |app: &App| {
+            app.get_selected_ref()
+                .unwrap()
+                .file
+                .file_name()
+                .unwrap()
+                .to_owned()
+        };
+        let first = file(&app);
+        assert!(screen(&mut app).contains("2 other definitions named `new`"));
+        let mut seen = vec![first.clone()];
+        for _ in 0..3 {
+            drive(&mut app, vec![key(KeyCode::Char('n'), KeyModifiers::ALT)]).await;
+            seen.push(file(&app));
+        }
+        // Every one in turn, then around again
+        let mut distinct = seen[..3].to_vec();
+        distinct.sort();
+        assert_eq!(distinct, ["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(seen[3], first);
+    }
+
+    #[tokio::test]
+    async fn enter_fires_its_configured_action() {
+        let mut app = app(PARSERS);
+        app.enter_action = Action::Print;
+        let mut events = typed("print");
+        events.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        let picked = drive(&mut app, events).await.unwrap();
+        // Printing closes the picker with the selection, which is printed on the way out
+        assert_eq!(picked[0].name, "print");
+        assert_eq!(app.printed, picked);
+    }
+
+    #[tokio::test]
+    async fn alt_enter_fires_its_configured_action_and_stays_open() {
+        let mut app = app(PARSERS);
+        app.alt_enter_action = Action::Print;
+        let mut events = typed("print");
+        events.push(key(KeyCode::Enter, KeyModifiers::ALT));
+        assert_eq!(drive(&mut app, events).await, None);
+        let printed: Vec<&str> = app.printed.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(printed, ["print"]);
+    }
+
+    #[test]
+    fn equal_scores_rank_earlier_matches_first() {
+        let mut app = app(PARSERS);
+        let with_sig = |name: &str, sig: &str| Ref {
+            match_sig: sig.into(),
+            ..r(name, RefKind::Fn, "lib.rs", 1, 1)
+        };
+        let early = with_sig("load_config", "fn load_config()");
+        let late = with_sig("preload", "fn preload()");
+        let query = Query::parse("load");
+        // As if the matcher scored both the same
+        let ranked = |app: &App, r: &Ref| {
+            let found = app.match_score(&query, r).unwrap();
+            app.rank(&query, r, TextMatch { score: 50, ..found }, 0)
+        };
+        let (early_prio, late_prio) = (ranked(&app, &early), ranked(&app, &late));
+        assert_eq!(early_prio.map(|prio| prio.match_start), Some(Reverse(3)));
+        assert_eq!(late_prio.map(|prio| prio.match_start), Some(Reverse(6)));
+        assert!(early_prio > late_prio);
+
+        // Left to the sort order without the tie-break
+        app.position_tie_break = false;
+        assert_eq!(ranked(&app, &early), ranked(&app, &late));
+    }
+
+    #[test]
+    fn alias_matches_start_where_the_alias_matches() {
+        let app = app(PARSERS);
+        let aliased = Ref {
+            match_sig: "fn run()".into(),
+            aliases: vec!["reload".into()],
+            ..r("run", RefKind::Fn, "lib.rs", 1, 1)
+        };
+        let late = Ref {
+            match_sig: "fn preload()".into(),
+            ..r("preload", RefKind::Fn, "lib.rs", 1, 1)
+        };
+        let query = Query::parse("load");
+        let found = app.match_score(&query, &aliased).unwrap();
+        assert_eq!(found.start, Reverse(2));
+        // Ahead of a signature matching later, rather than last
+        let found = TextMatch { score: 50, ..found };
+        let late_found = TextMatch {
+            score: 50,
+            ..app.match_score(&query, &late).unwrap()
+        };
+        assert!(app.rank(&query, &aliased, found, 0) > app.rank(&query, &late, late_found, 0));
+    }
+
+    const OUTLINED: &[(&str, &str)] = &[
+        ("a.rs", "fn alpha() {} fn apex() {}"),
+        ("b.rs", "fn beta() {}"),
+    ];
+
+    #[tokio::test]
+    async fn outline_restricts_to_the_selected_file() {
+        let mut app = app(OUTLINED);
+        drive(&mut app, typed("apex")).await;
+        let ctrl_u = key(KeyCode::Char('u'), KeyModifiers::CONTROL);
+        drive(
+            &mut app,
+            vec![key(KeyCode::Char('o'), KeyModifiers::ALT), ctrl_u.clone()],
+        )
+        .await;
+        let mut names = result_names(&app);
+        names.sort();
+        assert_eq!(names, ["alpha", "apex"]);
+        assert!(screen(&mut app).contains("[outline: a.rs]"));
+
+        // And back to the whole index
+        drive(&mut app, vec![key(KeyCode::Char('o'), KeyModifiers::ALT)]).await;
+        assert_eq!(app.outline, None);
+        assert_eq!(result_names(&app).len(), 3);
+    }
+
+    /// A select callback that takes a minute to finish.
+    fn slow_callback(app: &mut App) {
+        app.select_callback = Some(Box::new(|_: Vec<Ref>| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        }));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn slow_callbacks_time_out_and_leave_the_picker_open() {
+        let mut app = app(PARSERS);
+        slow_callback(&mut app);
+        // Long enough to outlast drawing the first previews of a debug build
+        app.callback_timeout = Duration::from_millis(500);
+        let mut events = vec![key(KeyCode::Enter, KeyModifiers::NONE)];
+        events.extend(typed("pr"));
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        // Typing on while the callback runs, then idle past its timeout
+        let picked = app
+            .run_with(&mut terminal, &mut then_idle(events))
+            .await
+            .unwrap();
+        assert_eq!(picked, None);
+        assert!(app.pending.is_none());
+        assert_eq!(app.input, "pr");
+        let screen = drawn(&terminal);
+        assert!(
+            screen.contains("Gave up on the editor after 500ms"),
+            "{screen}"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn esc_cancels_a_running_callback() {
+        let mut app = app(PARSERS);
+        slow_callback(&mut app);
+        let events = vec![
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            key(KeyCode::Esc, KeyModifiers::NONE),
+        ];
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        let picked = app
+            .run_with(&mut terminal, &mut then_idle(events))
+            .await
+            .unwrap();
+        // The first Esc only cancels, the picker is closed by the one after the idle turns
+        assert_eq!(picked, None);
+        assert!(app.pending.is_none());
+        assert!(drawn(&terminal).contains("cancelled"));
+    }
+
+    fn on_entry(app: &App) -> bool {
+        let i = app.search_result_state.selected().unwrap();
+        app.rows()[i].is_selectable()
+    }
+
+    /// Press `code` four times, returning the names selected on the way.
+    async fn walk(app: &mut App, code: KeyCode) -> Vec<String> {
+        let mut visited = Vec::new();
+        for _ in 0..4 {
+            drive(app, vec![key(code, KeyModifiers::NONE)]).await;
+            assert!(on_entry(app));
+            visited.push(app.get_selected_ref().unwrap().name);
+        }
+        visited
+    }
+
+    #[tokio::test]
+    async fn navigation_by_kind_skips_section_headers() {
+        let mut app = app(&[("lib.rs", "fn a() {} fn b() {} struct S; trait X {}")]);
+        app.by_kind = true;
+        app.search_result_state.select(None);
+        app.recompute_results();
+        // The best match, at the end as structs come last
+        assert!(on_entry(&app));
+        assert_eq!(app.get_selected_ref().unwrap().name, "S");
+
+        // Headers are skipped both ways, and the ends kept
+        assert_eq!(walk(&mut app, KeyCode::Up).await, ["X", "b", "a", "a"]);
+        assert_eq!(walk(&mut app, KeyCode::Down).await, ["b", "X", "S", "S"]);
+    }
+
+    #[tokio::test]
+    async fn selection_stays_on_a_result_when_narrowing() {
+        let mut app = app(PARSERS);
+        let mut events = vec![key(KeyCode::Down, KeyModifiers::NONE); 4];
+        events.extend(typed("pars"));
+        drive(&mut app, events).await;
+        // Down to the last of five, then only two are left
+        assert_eq!(result_names(&app).len(), 2);
+        let selected = app.search_result_state.selected().unwrap();
+        assert!(selected < 2, "{selected}");
+        assert!(app.get_selected_ref().unwrap().name.starts_with("parse_"));
+
+        // And on Enter it is a real ref that is picked
+        app.enter_action = Action::Print;
+        let picked = drive(&mut app, vec![key(KeyCode::Enter, KeyModifiers::NONE)]).await;
+        assert_eq!(picked.map(|picked| picked.len()), Some(1));
+    }
+
+    #[test]
+    fn new_indexes_the_current_directory() {
+        let app = App::new().unwrap();
+        assert_eq!(
+            app.root(),
+            env::current_dir().unwrap().canonicalize().unwrap()
+        );
+        assert!(app.input.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_to_open_a_file_keeps_the_picker_running() {
+        let mut app = app(&[("a.rs", "fn alpha() {}")]);
+        fs::remove_file(app._dir.join("a.rs")).unwrap();
+        let mut events = typed("alpha");
+        events.push(key(KeyCode::Char('o'), KeyModifiers::CONTROL));
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        let picked = app
+            .run_with(&mut terminal, &mut then_idle(events))
+            .await
+            .unwrap();
+        assert_eq!(picked, None);
+        let screen = drawn(&terminal);
+        assert!(screen.contains("failed to open "), "{screen}");
     }
 }
@@ -0,0 +1,373 @@
+use proc_macro2::{TokenStream, TokenTree};
+use syn::{
+    Attribute, Expr, ExprLit, ForeignItem, ImplItemFn, Item, Lit, LitStr, Meta, Path, Token,
+    parenthesized, parse::Parser, punctuated::Punctuated,
+};
+
+pub trait HasAttrs {
+    fn attrs(&self) -> &[Attribute];
+}
+
+impl HasAttrs for Item {
+    fn attrs(&self) -> &[Attribute] {
+        match self {
+            Item::Const(item) => &item.attrs,
+            Item::Enum(item) => &item.attrs,
+            Item::ExternCrate(item) => &item.attrs,
+            Item::Fn(item) => &item.attrs,
+            Item::ForeignMod(item) => &item.attrs,
+            Item::Impl(item) => &item.attrs,
+            Item::Macro(item) => &item.attrs,
+            Item::Mod(item) => &item.attrs,
+            Item::Static(item) => &item.attrs,
+            Item::Struct(item) => &item.attrs,
+            Item::Trait(item) => &item.attrs,
+            Item::TraitAlias(item) => &item.attrs,
+            Item::Type(item) => &item.attrs,
+            Item::Union(item) => &item.attrs,
+            Item::Use(item) => &item.attrs,
+            _ => &[],
+        }
+    }
+}
+
+impl HasAttrs for ForeignItem {
+    fn attrs(&self) -> &[Attribute] {
+        match self {
+            ForeignItem::Fn(item) => &item.attrs,
+            ForeignItem::Static(item) => &item.attrs,
+            ForeignItem::Type(item) => &item.attrs,
+            ForeignItem::Macro(item) => &item.attrs,
+            _ => &[],
+        }
+    }
+}
+
+impl HasAttrs for ImplItemFn {
+    fn attrs(&self) -> &[Attribute] {
+        &self.attrs
+    }
+}
+
+/// `attrs` with every `#[cfg_attr(pred, attr, ..)]` replaced by the attributes it applies, each
+/// with the predicate it depends on, e.g. `feature = "serde"`, or `None` if it always applies.
+/// The predicates of nested ones are combined into `all(..)`. A malformed `cfg_attr` is dropped,
+/// the compiler would reject it anyway.
+fn expand_cfg_attrs(attrs: &[Attribute]) -> Vec<(Attribute, Option<String>)> {
+    let mut expanded = Vec::new();
+    for attr in attrs {
+        expand_cfg_attr(attr.clone(), None, &mut expanded);
+    }
+    expanded
+}
+
+fn expand_cfg_attr(
+    attr: Attribute,
+    condition: Option<String>,
+    expanded: &mut Vec<(Attribute, Option<String>)>,
+) {
+    let list = match &attr.meta {
+        Meta::List(list) if list.path.is_ident("cfg_attr") => list,
+        _ => {
+            expanded.push((attr, condition));
+            return;
+        }
+    };
+    // The predicate is everything up to the first comma, commas inside `all(..)` are nested in
+    // a group
+    let mut tokens = list.tokens.clone().into_iter();
+    let pred: TokenStream = tokens
+        .by_ref()
+        .take_while(|tt| !matches!(tt, TokenTree::Punct(punct) if punct.as_char() == ','))
+        .collect();
+    let Ok(metas) = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(tokens.collect()) else {
+        return;
+    };
+    if pred.is_empty() {
+        return;
+    }
+    let pred = token_text(pred);
+    let condition = Some(match condition {
+        Some(outer) => format!("all({outer}, {pred})"),
+        None => pred,
+    });
+    for meta in metas {
+        let attr = Attribute {
+            meta,
+            ..attr.clone()
+        };
+        expand_cfg_attr(attr, condition.clone(), expanded);
+    }
+}
+
+/// The source text of `tokens`, or how they print if the source isn't at hand.
+fn token_text(tokens: TokenStream) -> String {
+    let mut trees = tokens.clone().into_iter();
+    let first = trees.next().map(|tt| tt.span());
+    let last = trees.last().map(|tt| tt.span()).or(first);
+    first
+        .zip(last)
+        .and_then(|(first, last)| first.join(last)?.source_text())
+        .unwrap_or_else(|| tokens.to_string())
+}
+
+/// Whether the attributes mark test-only code, i.e. `#[test]` or a `#[cfg(..)]` that requires
+/// `test`.
+pub fn is_test(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("test")
+            || (attr.path().is_ident("cfg")
+                && attr
+                    .parse_args::<Meta>()
+                    .is_ok_and(|pred| requires_test(&pred)))
+    })
+}
+
+/// Whether a cfg predicate can only hold under `cfg(test)`.
+fn requires_test(pred: &Meta) -> bool {
+    match pred {
+        Meta::Path(path) => path.is_ident("test"),
+        Meta::List(list) if list.path.is_ident("all") => list
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .is_ok_and(|preds| preds.iter().any(requires_test)),
+        _ => false,
+    }
+}
+
+/// The predicate of the `#[cfg(..)]` attributes as written, e.g. `feature = "x"`. Several of
+/// them are combined into one `all(..)` since they all have to hold.
+pub fn cfg_predicate(attrs: &[Attribute]) -> Option<String> {
+    let preds: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .filter_map(|attr| attr.meta.require_list().ok())
+        .map(|list| {
+            // Token streams print with extra spaces, e.g. `not (test)`, prefer the source
+            list.delimiter
+                .span()
+                .join()
+                .source_text()
+                .and_then(|text| Some(text.strip_prefix('(')?.strip_suffix(')')?.to_owned()))
+                .unwrap_or_else(|| list.tokens.to_string())
+        })
+        .collect();
+    match preds.len() {
+        0 => None,
+        1 => preds.into_iter().next(),
+        _ => Some(format!("all({})", preds.join(", "))),
+    }
+}
+
+/// Alternate names an item goes by, from `#[doc(alias = "..")]`, `#[doc(alias("..", ".."))]`
+/// and `#[serde(rename = "..")]`.
+pub fn aliases(attrs: &[Attribute]) -> Vec<String> {
+    let mut aliases = Vec::new();
+    for (attr, _) in expand_cfg_attrs(attrs) {
+        let (arg, is_doc) = if attr.path().is_ident("doc") {
+            ("alias", true)
+        } else if attr.path().is_ident("serde") {
+            ("rename", false)
+        } else {
+            continue;
+        };
+        if !matches!(attr.meta, Meta::List(_)) {
+            continue;
+        }
+        // Arguments we don't consume make parsing bail early, the aliases before that still count
+        let _ = attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident(arg) {
+                return Ok(());
+            }
+            if meta.input.peek(Token![=]) {
+                aliases.push(meta.value()?.parse::<LitStr>()?.value());
+            } else if is_doc {
+                let content;
+                parenthesized!(content in meta.input);
+                let names = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                aliases.extend(names.iter().map(LitStr::value));
+            }
+            Ok(())
+        });
+    }
+    aliases
+}
+
+/// The text of the doc comments, i.e. `/// ..` and `#[doc = ".."]`, one line each.
+pub fn docs(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = expand_cfg_attrs(attrs)
+        .iter()
+        .map(|(attr, _)| attr)
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta.require_name_value().ok()?.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(text),
+                ..
+            }) => Some(text.value()),
+            _ => None,
+        })
+        .collect();
+    // `/// text` keeps the space after the slashes
+    let text = lines
+        .iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// The traits named in `#[derive(..)]` attributes, as written, e.g. `Clone` or
+/// `serde::Serialize`, each with the predicate of the `#[cfg_attr(..)]` it is derived under.
+pub fn derives(attrs: &[Attribute]) -> Vec<(String, Option<String>)> {
+    expand_cfg_attrs(attrs)
+        .into_iter()
+        .filter(|(attr, _)| attr.path().is_ident("derive"))
+        .filter_map(|(attr, condition)| {
+            let paths = attr
+                .parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated)
+                .ok()?;
+            Some(paths.into_iter().map(move |path| (path, condition.clone())))
+        })
+        .flatten()
+        .map(|(path, condition)| {
+            let name = path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::");
+            (name, condition)
+        })
+        .collect()
+}
+
+/// Whether the attributes contain `#[doc(hidden)]`.
+pub fn is_doc_hidden(attrs: &[Attribute]) -> bool {
+    expand_cfg_attrs(attrs).iter().any(|(attr, _)| {
+        let mut hidden = false;
+        if attr.path().is_ident("doc") && matches!(attr.meta, Meta::List(_)) {
+            // Other doc arguments may carry values we don't consume, those errors don't matter
+            let _ = attr.parse_nested_meta(|meta| {
+                hidden |= meta.path.is_ident("hidden");
+                Ok(())
+            });
+        }
+        hidden
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The attributes of the single item in `src`.
+    fn attrs_of(src: &str) -> Vec<Attribute> {
+        let item: Item = syn::parse_str(src).unwrap();
+        item.attrs().to_vec()
+    }
+
+    fn cfg_of(src: &str) -> Option<String> {
+        cfg_predicate(&attrs_of(src))
+    }
+
+    #[test]
+    fn cfg_predicates_are_kept_as_written() {
+        assert_eq!(
+            cfg_of(r#"#[cfg(feature = "x")] fn f() {}"#).as_deref(),
+            Some(r#"feature = "x""#)
+        );
+        assert_eq!(
+            cfg_of("#[cfg(not(target_os = \"windows\"))] fn f() {}").as_deref(),
+            Some("not(target_os = \"windows\")")
+        );
+        assert_eq!(
+            cfg_of("#[cfg(any(unix, all(test, feature = \"a\")))] struct S;").as_deref(),
+            Some("any(unix, all(test, feature = \"a\"))")
+        );
+    }
+
+    #[test]
+    fn several_cfgs_are_combined() {
+        assert_eq!(
+            cfg_of("#[cfg(unix)] #[inline] #[cfg(feature = \"fast\")] fn f() {}").as_deref(),
+            Some("all(unix, feature = \"fast\")")
+        );
+    }
+
+    #[test]
+    fn items_without_cfg_have_no_predicate() {
+        assert_eq!(
+            cfg_of("#[inline] #[cfg_attr(test, derive(Debug))] fn f() {}"),
+            None
+        );
+        assert_eq!(cfg_of("fn f() {}"), None);
+    }
+
+    #[test]
+    fn aliases_come_from_doc_alias_and_serde_rename() {
+        assert_eq!(
+            aliases(&attrs_of(r#"#[doc(alias = "malloc")] fn allocate() {}"#)),
+            ["malloc"]
+        );
+        assert_eq!(
+            aliases(&attrs_of(r#"#[doc(alias("a", "b"))] fn f() {}"#)),
+            ["a", "b"]
+        );
+        assert_eq!(
+            aliases(&attrs_of(r#"#[serde(rename = "userName")] struct User;"#)),
+            ["userName"]
+        );
+        assert_eq!(
+            aliases(&attrs_of(
+                r#"#[cfg_attr(feature = "serde", serde(rename = "wire"))] struct S;"#
+            )),
+            ["wire"]
+        );
+        assert!(aliases(&attrs_of("#[doc = \"plain docs\"] fn f() {}")).is_empty());
+    }
+
+    #[test]
+    fn cfg_attr_derives_carry_their_predicate() {
+        let attrs = attrs_of(
+            r#"#[derive(Clone)]
+            #[cfg_attr(feature = "serde", derive(serde::Serialize), cfg_attr(test, derive(Debug)))]
+            struct S;"#,
+        );
+        assert_eq!(
+            derives(&attrs),
+            [
+                ("Clone".into(), None),
+                (
+                    "serde::Serialize".into(),
+                    Some(r#"feature = "serde""#.into())
+                ),
+                (
+                    "Debug".into(),
+                    Some(r#"all(feature = "serde", test)"#.into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn cfg_attr_applies_docs_and_doc_hidden() {
+        let attrs =
+            attrs_of(r#"#[cfg_attr(docsrs, doc = "Only on docs.rs", doc(hidden))] fn f() {}"#);
+        assert_eq!(docs(&attrs).as_deref(), Some("Only on docs.rs"));
+        assert!(is_doc_hidden(&attrs));
+    }
+
+    #[test]
+    fn malformed_cfg_attrs_are_dropped() {
+        for src in [
+            "#[cfg_attr] fn f() {}",
+            "#[cfg_attr()] fn f() {}",
+            "#[cfg_attr(, derive(Debug))] fn f() {}",
+            "#[cfg_attr(test, = 1)] fn f() {}",
+        ] {
+            let attrs = attrs_of(src);
+            assert!(derives(&attrs).is_empty(), "{src}");
+            assert_eq!(cfg_predicate(&attrs), None, "{src}");
+        }
+    }
+}
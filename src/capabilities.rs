@@ -0,0 +1,43 @@
+use bat::PrettyPrinter;
+use clap::ValueEnum;
+use tourust::{IndexOptions, error::Result};
+
+use crate::{app::Matcher, config::Config, theme::Theme};
+
+/// Print what this build can do and which defaults are active, to help debugging behavior.
+pub fn capabilities(opts: &IndexOptions, matcher: Matcher) -> Result<()> {
+    let list = |names: Vec<String>| names.join(", ");
+    println!("tourust {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "config: {}",
+        Config::file().map_or("unavailable".into(), |file| file.display().to_string())
+    );
+    println!(
+        "matcher: {} (available: {})",
+        matcher,
+        list(
+            Matcher::value_variants()
+                .iter()
+                .map(Matcher::to_string)
+                .collect()
+        )
+    );
+    println!("backends: nvim (unix socket, tcp), open, exec");
+    println!("file watching: no");
+    println!("parallel indexing: no");
+    println!("extensions: {}", list(opts.extensions.clone()));
+    println!(
+        "indexed kinds: {}",
+        list(opts.kinds.iter().map(|kind| kind.to_string()).collect())
+    );
+    println!("themes: {}", Theme::NAMES.join(", "));
+    println!(
+        "bat: {}",
+        option_env!("TOURUST_BAT_VERSION").unwrap_or("unknown")
+    );
+    println!(
+        "syntax themes: {}",
+        list(PrettyPrinter::new().themes().map(str::to_owned).collect())
+    );
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::{collections::BTreeMap, path::Path};
+
+use tourust::{
+    IndexOptions, Indexer, RefKind,
+    error::{Error, Result},
+};
+
+use crate::cli::CheckArgs;
+
+pub fn check(args: CheckArgs, root: &Path, opts: IndexOptions) -> Result<()> {
+    let mut indexer = Indexer::new(root, opts);
+
+    let mut counts = BTreeMap::<RefKind, usize>::new();
+    let mut failures = Vec::new();
+    for (file, refs) in &mut indexer {
+        match refs {
+            Ok(refs) => {
+                for r in refs {
+                    *counts.entry(r.kind).or_default() += 1;
+                }
+            }
+            Err(err) => failures.push((file, err)),
+        }
+    }
+
+    let progress = indexer.progress();
+    println!(
+        "Indexed {} of {} files, {} symbols",
+        progress.total - failures.len(),
+        progress.total,
+        counts.values().sum::<usize>()
+    );
+    for (kind, count) in &counts {
+        println!("  {kind:<8} {count}");
+    }
+
+    if !failures.is_empty() {
+        println!("Failed to parse:");
+        for (file, err) in &failures {
+            let file = file.strip_prefix(root).unwrap_or(file);
+            println!("  {}: {}", file.display(), err);
+        }
+        if !args.allow_failures {
+            return Err(Error::ParseFailures(failures.len()));
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,97 @@
-use std::path::PathBuf;
+use std::{env, ffi::OsString, io, path::PathBuf, str::FromStr};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use tourust::{
+    CargoTarget, DEFAULT_MAX_FILE_SIZE, IndexOptions, RefKind, build_globs, error::Result,
+};
+
+use crate::{
+    app::{Matcher, SortOrder},
+    config::Config,
+    exec::ExecTemplate,
+    nvim::Target,
+};
 
 #[derive(Debug, Parser)]
+#[command(version)]
 pub struct Cli {
+    #[command(flatten)]
+    pub index: IndexArgs,
+    /// Directory to pick symbols from. Without the flag `TOURUST_ROOT` is used if set, e.g. by an
+    /// editor plugin that always wants the project root, then the current directory
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+    /// Index the newline separated files read from stdin instead of searching the root, e.g.
+    /// `fd -e rs | tourust --stdin`
+    #[arg(long, conflicts_with = "git_changed")]
+    pub stdin: bool,
+    /// Index only the files with uncommitted changes, as listed by `git diff`
+    #[arg(long)]
+    pub git_changed: bool,
+    /// With `--git-changed`, take the staged changes instead of the unstaged ones
+    #[arg(long, requires = "git_changed")]
+    pub staged: bool,
+    /// Start out with this query
+    #[arg(short, long)]
+    pub query: Option<String>,
+    /// Skip the picker and act on the best match for `--query` right away, like a goto
+    #[arg(long, requires = "query")]
+    pub open_first: bool,
+    /// Only show `pub` and `pub(crate)` items, toggle with Ctrl-v
+    #[arg(long)]
+    pub public_only: bool,
+    /// Order of equally scored results, cycle with Ctrl-s
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: SortOrder,
+    /// How strongly often and recently selected symbols are boosted, 0 disables it
+    #[arg(long, default_value_t = 2.0)]
+    pub frecency_weight: f64,
+    /// List at most this many of the best results, 0 lists all of them
+    #[arg(long, default_value_t = 500, value_name = "N")]
+    pub max_results: usize,
+    /// Fuzzy matching algorithm [default: clangd]
+    #[arg(long, value_enum)]
+    pub matcher: Option<Matcher>,
+    /// Hide matches scoring below this, adjust with Alt-Up/Down
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub min_score: i64,
+    /// Leave equally scored matches to `--sort` instead of ranking those matching earlier in
+    /// their signature first
+    #[arg(long)]
+    pub no_position_tie_break: bool,
+    /// Lines of source shown above and below the symbol's item in the preview [default: 5
+    /// above, 2 below]
+    #[arg(long, value_name = "N")]
+    pub context: Option<usize>,
+    /// Show the preview as plain text instead of highlighting it, which is much cheaper on slow
+    /// machines or over ssh
+    #[arg(long)]
+    pub no_preview_highlight: bool,
+    /// Wrap long lines in the preview instead of cutting them off at its edge
+    #[arg(long)]
+    pub wrap: bool,
+    /// List the results in sections per kind, functions first, then methods, modules and so on
+    #[arg(long)]
+    pub by_kind: bool,
+    /// Draw in this many lines below the prompt instead of the whole screen, keeping the
+    /// scrollback above
+    #[arg(long, value_name = "LINES", num_args = 0..=1, default_missing_value = "20")]
+    pub inline: Option<u16>,
+    /// Run this command on each selected symbol once the picker closes, e.g.
+    /// `--exec 'kak -e "edit {file} {line} {column}"'`. `{file}`, `{line}`, `{column}` and
+    /// `{name}` are replaced by the symbol's, after splitting the command into arguments
+    #[arg(long, value_name = "COMMAND")]
+    pub exec: Option<ExecTemplate>,
+    /// Log at this level, e.g. `debug` or `tourust=trace`, to `--log-file` or a file in the
+    /// current directory
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+    /// Log to this file, at `info` unless `--log-level` says otherwise
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+    /// Print the version, active defaults and supported backends, then exit
+    #[arg(long)]
+    pub capabilities: bool,
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -11,10 +99,293 @@ pub struct Cli {
 #[derive(Debug, Subcommand)]
 pub enum Command {
     Nvim(NvimArgs),
+    /// Dump every indexed symbol to stdout without entering the TUI
+    Export(ExportArgs),
+    /// Write a ctags-compatible tags file for every indexed symbol
+    Tags(TagsArgs),
+    /// Index without entering the TUI and report what was found and what failed
+    Check(CheckArgs),
+    /// Print the innermost symbol whose item contains a position, e.g. `src/app.rs:120`
+    Locate(LocateArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct IndexArgs {
+    /// Also index `#[cfg(test)]` modules and `#[test]` functions
+    #[arg(long, global = true)]
+    pub include_tests: bool,
+    /// Leave out `#[doc(hidden)]` items
+    #[arg(long, global = true)]
+    pub hide_doc_hidden: bool,
+    /// Also index named struct fields and enum variants
+    #[arg(long, global = true)]
+    pub fields: bool,
+    /// Also index the files pulled in with `include!("..")`, e.g. generated code
+    #[arg(long, global = true)]
+    pub follow_includes: bool,
+    /// Also index items declared inside function bodies, e.g. helper fns, which takes longer
+    #[arg(long, global = true)]
+    pub nested_items: bool,
+    /// Also index the functions, statics and types declared in `extern "C" { .. }` blocks
+    #[arg(long, global = true)]
+    pub foreign_items: bool,
+    /// Leave out the `impl Foo` entries of impl blocks, keeping the functions inside them
+    #[arg(long, global = true, conflicts_with = "no_impl_items")]
+    pub no_impl_blocks: bool,
+    /// Leave out the functions of impl blocks, keeping the `impl Foo` entries
+    #[arg(long, global = true)]
+    pub no_impl_items: bool,
+    /// Skip files larger than this many bytes, usually generated code, 0 indexes every file
+    #[arg(long, global = true, value_name = "BYTES", default_value_t = DEFAULT_MAX_FILE_SIZE)]
+    pub max_file_size: u64,
+    /// Only search this many directory levels deep, 1 indexes just the files directly in the
+    /// search root, for a quick look at the top of a huge tree
+    #[arg(long, global = true, value_name = "N")]
+    pub max_depth: Option<usize>,
+    /// Skip files whose path relative to the search root matches this glob, may be repeated
+    #[arg(long, global = true, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Comma separated kinds of items to index, everything but `use` by default
+    #[arg(long, global = true, value_delimiter = ',', value_name = "KINDS")]
+    pub index_kinds: Option<Vec<RefKind>>,
+    /// Comma separated Cargo targets to index, e.g. `lib,bin`, every file by default
+    #[arg(long, global = true, value_delimiter = ',', value_name = "TARGETS")]
+    pub target: Vec<CargoTarget>,
+}
+
+impl Command {
+    /// Whether the subcommand indexes and exits without a picker, so it has no use for a file
+    /// list from `--stdin` or `--git-changed`.
+    pub fn is_batch(&self) -> bool {
+        !matches!(self, Command::Nvim(_))
+    }
+
+    /// The subcommand as typed.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Nvim(_) => "nvim",
+            Command::Export(_) => "export",
+            Command::Tags(_) => "tags",
+            Command::Check(_) => "check",
+            Command::Locate(_) => "locate",
+        }
+    }
+}
+
+/// Environment variable holding the search root used when `--root` isn't given.
+pub const ROOT_ENV: &str = "TOURUST_ROOT";
+
+impl Cli {
+    /// The directory to search: `--root`, then [`ROOT_ENV`] unless it is empty, then the
+    /// current one.
+    pub fn root(&self) -> io::Result<PathBuf> {
+        self.root_from(env::var_os(ROOT_ENV), env::current_dir)
+    }
+
+    /// The same as [`Cli::root`] with the environment variable's value and the current
+    /// directory passed in.
+    fn root_from(
+        &self,
+        env_root: Option<OsString>,
+        cwd: impl FnOnce() -> io::Result<PathBuf>,
+    ) -> io::Result<PathBuf> {
+        match self
+            .root
+            .clone()
+            .or_else(|| env_root.filter(|root| !root.is_empty()).map(PathBuf::from))
+        {
+            Some(root) => Ok(root),
+            None => cwd(),
+        }
+    }
+}
+
+impl IndexArgs {
+    pub fn options(&self, config: &Config) -> Result<IndexOptions> {
+        let mut kinds = self
+            .index_kinds
+            .clone()
+            .or_else(|| config.index_kinds.clone())
+            .unwrap_or_else(IndexOptions::default_kinds);
+        if self.no_impl_blocks || config.impl_blocks == Some(false) {
+            kinds.retain(|kind| *kind != RefKind::Impl);
+        }
+        Ok(IndexOptions {
+            include_tests: self.include_tests,
+            hide_doc_hidden: self.hide_doc_hidden,
+            fields: self.fields,
+            follow_includes: self.follow_includes,
+            nested_items: self.nested_items,
+            foreign_items: self.foreign_items,
+            impl_items: !self.no_impl_items && config.impl_items.unwrap_or(true),
+            max_file_size: self.max_file_size,
+            max_depth: self.max_depth,
+            targets: self.target.clone(),
+            exclude: build_globs(&self.exclude)?,
+            kinds,
+            ..Default::default()
+        })
+    }
 }
 
 #[derive(Args, Debug)]
+#[command(group(ArgGroup::new("address").required(true).multiple(false)))]
 pub struct NvimArgs {
+    /// Unix socket neovim is listening on
+    #[arg(long, group = "address")]
+    pub socket: Option<PathBuf>,
+    /// Address neovim is listening on over TCP
+    #[arg(long, value_name = "HOST:PORT", group = "address")]
+    pub tcp: Option<String>,
+    /// Open symbols in the window with this id, see `:echo win_getid()`, instead of the one
+    /// used last
+    #[arg(long, value_name = "ID")]
+    pub window: Option<i64>,
+}
+
+impl NvimArgs {
+    pub fn target(&self) -> Target {
+        match (&self.socket, &self.tcp) {
+            (Some(socket), _) => Target::Socket(socket.to_owned()),
+            (None, Some(addr)) => Target::Tcp(addr.to_owned()),
+            (None, None) => unreachable!("clap requires one of --socket and --tcp"),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    pub format: ExportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct TagsArgs {
+    /// Where to write the tags file, stdout if omitted
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Exit successfully even if some files failed to parse
     #[arg(long)]
-    pub socket: PathBuf,
+    pub allow_failures: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LocateArgs {
+    /// The position as `FILE:LINE`, relative to the current directory
+    pub location: Location,
+    /// Open the symbol's file in the default app instead of printing it
+    #[arg(long)]
+    pub open: bool,
+}
+
+/// A line in a file, parsed from `FILE:LINE`.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        // Split at the last colon, the file name may contain one
+        let (file, line) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("expected FILE:LINE, got {s:?}"))?;
+        let line = line
+            .parse()
+            .map_err(|err| format!("invalid line {line:?}: {err}"))?;
+        Ok(Self {
+            file: file.into(),
+            line,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ExportFormat {
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(args: &[&str], config: &str) -> Vec<RefKind> {
+        let cli = Cli::parse_from(["tourust"].iter().chain(args));
+        let config: Config = toml::from_str(config).unwrap();
+        cli.index.options(&config).unwrap().kinds
+    }
+
+    #[test]
+    fn imports_are_left_out_by_default() {
+        assert!(!kinds(&[], "").contains(&RefKind::Use));
+    }
+
+    #[test]
+    fn config_kinds_bring_imports_back() {
+        assert_eq!(
+            kinds(&[], r#"index_kinds = ["fn", "use"]"#),
+            [RefKind::Fn, RefKind::Use]
+        );
+    }
+
+    #[test]
+    fn flag_kinds_take_precedence_over_the_config() {
+        assert_eq!(
+            kinds(
+                &["--index-kinds", "struct"],
+                r#"index_kinds = ["fn", "use"]"#
+            ),
+            [RefKind::Struct]
+        );
+    }
+
+    fn root(args: &[&str], env_root: Option<&str>) -> PathBuf {
+        let cli = Cli::parse_from(["tourust"].iter().chain(args));
+        cli.root_from(env_root.map(OsString::from), || Ok(PathBuf::from("/cwd")))
+            .unwrap()
+    }
+
+    #[test]
+    fn root_is_the_flag_then_the_env_then_the_cwd() {
+        assert_eq!(
+            root(&["--root", "/flag"], Some("/env")),
+            PathBuf::from("/flag")
+        );
+        assert_eq!(root(&[], Some("/env")), PathBuf::from("/env"));
+        assert_eq!(root(&[], None), PathBuf::from("/cwd"));
+        // Set but empty, as after `TOURUST_ROOT= tourust`
+        assert_eq!(root(&[], Some("")), PathBuf::from("/cwd"));
+    }
+
+    #[test]
+    fn impl_blocks_can_be_left_out() {
+        assert!(kinds(&[], "").contains(&RefKind::Impl));
+        assert!(!kinds(&["--no-impl-blocks"], "").contains(&RefKind::Impl));
+        assert!(!kinds(&[], "impl_blocks = false").contains(&RefKind::Impl));
+        assert!(kinds(&[], "impl_blocks = true").contains(&RefKind::Impl));
+    }
+
+    #[test]
+    fn impl_items_can_be_left_out() {
+        let impl_items = |args: &[&str], config: &str| {
+            let cli = Cli::parse_from(["tourust"].iter().chain(args));
+            let config: Config = toml::from_str(config).unwrap();
+            cli.index.options(&config).unwrap().impl_items
+        };
+        assert!(impl_items(&[], ""));
+        assert!(!impl_items(&["--no-impl-items"], ""));
+        assert!(!impl_items(&[], "impl_items = false"));
+        // Leaving out both would leave nothing of an impl
+        assert!(Cli::try_parse_from(["tourust", "--no-impl-blocks", "--no-impl-items"]).is_err());
+    }
 }
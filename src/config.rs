@@ -0,0 +1,86 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::Deserialize;
+use tourust::{
+    RefKind,
+    error::{Error, Result},
+};
+
+use crate::{
+    app::{Action, Matcher},
+    theme::ThemeConfig,
+};
+
+/// Settings read from `config.toml` in the tourust config dir. Command line flags take
+/// precedence over anything set here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Kinds of items to index, e.g. `["fn", "struct", "use"]`
+    pub index_kinds: Option<Vec<RefKind>>,
+    /// Index the `impl Foo` entries of impl blocks, on by default, see `--no-impl-blocks`
+    pub impl_blocks: Option<bool>,
+    /// Index the functions of impl blocks, on by default, see `--no-impl-items`
+    pub impl_items: Option<bool>,
+    /// Lines of source shown above and below the symbol's item in the preview
+    pub context: Option<usize>,
+    /// Wrap long lines in the preview, as with `--wrap`
+    pub wrap: bool,
+    /// Fuzzy matching algorithm, `"clangd"` or `"skim"`
+    pub matcher: Option<Matcher>,
+    /// Rank equally scored matches by how early in the signature they match, on by default,
+    /// see `--no-position-tie-break`
+    pub position_tie_break: Option<bool>,
+    /// Added to the score of each kind of ref, e.g. `{ enum = 10, use = -20 }`, in place of the
+    /// default weight of those kinds
+    pub kind_weights: HashMap<RefKind, i64>,
+    /// URL copied as a markdown link with Alt-l, `{file}` and `{line}` are replaced by the
+    /// selected symbol's location, e.g.
+    /// `"https://github.com/owner/repo/blob/main/{file}#L{line}"`. Without one the link is just
+    /// `file:line`.
+    pub link_template: Option<String>,
+    /// Colors of the TUI, a built in theme and/or single colors
+    pub theme: ThemeConfig,
+    /// What the confirm keys do
+    pub keys: KeysConfig,
+}
+
+/// The `[keys]` table of the config, e.g. `enter = "print"`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    /// Action of Enter, `"jump"`, `"print"`, `"copy"` or `"quickfix"`, by default jumping
+    pub enter: Option<Action>,
+    /// Action of Ctrl-Enter and Alt-Enter, which keep the picker open
+    pub alt_enter: Option<Action>,
+}
+
+impl Config {
+    pub fn file() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("tourust").join("config.toml"))
+    }
+
+    /// Load the config file, falling back to the defaults if there is none.
+    pub fn load() -> Result<Self> {
+        let Some(file) = Self::file().filter(|file| file.exists()) else {
+            return Ok(Self::default());
+        };
+        let src = fs::read_to_string(&file)?;
+        toml::from_str(&src).map_err(|err| Error::Config(file, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirm_keys_are_bound_to_named_actions() {
+        let config: Config =
+            toml::from_str("[keys]\nenter = \"print\"\nalt_enter = \"quickfix\"\n").unwrap();
+        assert_eq!(config.keys.enter, Some(Action::Print));
+        assert_eq!(config.keys.alt_enter, Some(Action::Quickfix));
+        assert_eq!(Config::default().keys.enter, None);
+        assert!(toml::from_str::<Config>("[keys]\nenter = \"launch\"\n").is_err());
+    }
+}
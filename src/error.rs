@@ -1,4 +1,4 @@
-use std::{fmt, io};
+use std::{fmt, io, path::PathBuf, time::Duration};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -9,8 +9,30 @@ pub enum Error {
     Bat(bat::error::Error),
     Logger(flexi_logger::FlexiLoggerError),
     Translate(ansi_to_tui::Error),
-    Utf8,
+    Json(serde_json::Error),
+    Glob(globset::Error),
+    Config(PathBuf, toml::de::Error),
+    /// A path had to be passed on as a string but isn't valid UTF-8
+    Utf8(PathBuf),
     NoWindow,
+    /// Could not connect to neovim at this address, even after retrying
+    Connect(String, io::Error),
+    /// The default application for this file could not be launched
+    Open(PathBuf, io::Error),
+    /// Indexing finished, but this many files could not be parsed
+    ParseFailures(usize),
+    /// Git failed, e.g. outside of a repository, with what it printed
+    Git(String),
+    /// No indexed item spans this line of the file
+    NoSymbolAt(PathBuf, usize),
+    /// The `--exec` command could not be started or failed
+    Exec(String, io::Error),
+    /// Nothing matches this query
+    NoMatch(String),
+    /// The editor didn't respond to a jump within this long
+    Timeout(Duration),
+    /// The task jumping to the selection panicked
+    Jump(tokio::task::JoinError),
 }
 
 impl fmt::Display for Error {
@@ -22,8 +44,22 @@ impl fmt::Display for Error {
             Self::Bat(err) => std::fmt::Display::fmt(err, f),
             Self::Logger(err) => std::fmt::Display::fmt(err, f),
             Self::Translate(err) => std::fmt::Display::fmt(err, f),
-            Self::Utf8 => write!(f, "Invalid utf-8 could not be parsed"),
+            Self::Json(err) => std::fmt::Display::fmt(err, f),
+            Self::Glob(err) => std::fmt::Display::fmt(err, f),
+            Self::Config(file, err) => write!(f, "Invalid config {}: {}", file.display(), err),
+            Self::Utf8(path) => write!(f, "Path is not valid utf-8: {}", path.display()),
             Self::NoWindow => write!(f, "No valid window found"),
+            Self::Connect(addr, err) => write!(f, "Failed to connect to {}: {}", addr, err),
+            Self::Open(file, err) => write!(f, "Failed to open {}: {}", file.display(), err),
+            Self::ParseFailures(count) => write!(f, "{count} file(s) failed to parse"),
+            Self::Git(message) => write!(f, "git failed: {message}"),
+            Self::NoSymbolAt(file, line) => {
+                write!(f, "No indexed symbol contains {}:{}", file.display(), line)
+            }
+            Self::Exec(program, err) => write!(f, "Failed to run {}: {}", program, err),
+            Self::NoMatch(query) => write!(f, "No symbol matches {:?}", query),
+            Self::Timeout(after) => write!(f, "Gave up on the editor after {:?}", after),
+            Self::Jump(err) => write!(f, "Failed to jump: {}", err),
         }
     }
 }
@@ -37,8 +73,22 @@ impl fmt::Debug for Error {
             Self::Bat(err) => std::fmt::Debug::fmt(err, f),
             Self::Logger(err) => std::fmt::Debug::fmt(err, f),
             Self::Translate(err) => std::fmt::Debug::fmt(err, f),
-            Self::Utf8 => write!(f, "Invalid utf-8 could not be parsed"),
+            Self::Json(err) => std::fmt::Debug::fmt(err, f),
+            Self::Glob(err) => std::fmt::Debug::fmt(err, f),
+            Self::Config(file, err) => write!(f, "Invalid config {}: {}", file.display(), err),
+            Self::Utf8(path) => write!(f, "Path is not valid utf-8: {}", path.display()),
             Self::NoWindow => write!(f, "No valid window found"),
+            Self::Connect(addr, err) => write!(f, "Failed to connect to {}: {}", addr, err),
+            Self::Open(file, err) => write!(f, "Failed to open {}: {}", file.display(), err),
+            Self::ParseFailures(count) => write!(f, "{count} file(s) failed to parse"),
+            Self::Git(message) => write!(f, "git failed: {message}"),
+            Self::NoSymbolAt(file, line) => {
+                write!(f, "No indexed symbol contains {}:{}", file.display(), line)
+            }
+            Self::Exec(program, err) => write!(f, "Failed to run {}: {}", program, err),
+            Self::NoMatch(query) => write!(f, "No symbol matches {:?}", query),
+            Self::Timeout(after) => write!(f, "Gave up on the editor after {:?}", after),
+            Self::Jump(err) => write!(f, "Failed to jump: {}", err),
         }
     }
 }
@@ -84,3 +134,15 @@ impl From<ansi_to_tui::Error> for Error {
         Error::Translate(value)
     }
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+impl From<globset::Error> for Error {
+    fn from(value: globset::Error) -> Self {
+        Error::Glob(value)
+    }
+}
@@ -0,0 +1,190 @@
+use std::{io, process::Command, str::FromStr};
+
+use log::debug;
+use tourust::{
+    Ref,
+    error::{Error, Result},
+};
+
+/// A command run on each selected ref once the picker has closed, from `--exec`. The template
+/// is split into arguments like a shell would, honoring quotes and backslashes, and `{file}`,
+/// `{line}`, `{column}` and `{name}` are filled in afterwards. So a file with spaces in its
+/// path stays one argument, and nothing in it is ever interpreted by a shell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecTemplate {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ExecTemplate {
+    /// The command for `r`, with its placeholders filled in.
+    fn command(&self, r: &Ref) -> Command {
+        let fill = |word: &str| {
+            word.replace("{file}", &r.file.to_string_lossy())
+                .replace("{line}", &r.line.to_string())
+                // Columns count from 0 in the index but from 1 in every editor
+                .replace("{column}", &(r.column + 1).to_string())
+                .replace("{name}", &r.name)
+        };
+        let mut command = Command::new(fill(&self.program));
+        command.args(self.args.iter().map(|arg| fill(arg)));
+        command
+    }
+
+    /// Run the command for each ref in turn, waiting for it to exit, e.g. for an editor
+    /// taking over the terminal. Stops at the first one that fails.
+    pub fn run(&self, refs: &[Ref]) -> Result<()> {
+        for r in refs {
+            let mut command = self.command(r);
+            debug!("running: {command:?}");
+            let status = command
+                .status()
+                .map_err(|err| Error::Exec(self.program.clone(), err))?;
+            if !status.success() {
+                let err = io::Error::other(format!("exited with {status}"));
+                return Err(Error::Exec(self.program.clone(), err));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ExecTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut words = split_words(s)?.into_iter();
+        let program = words.next().ok_or("the command is empty")?;
+        Ok(Self {
+            program,
+            args: words.collect(),
+        })
+    }
+}
+
+/// Split `s` into words at whitespace the way a POSIX shell does, without expanding anything.
+/// Single quotes keep everything up to the next one as is, double quotes keep whitespace and
+/// only let a backslash escape `"` and `\`, and a backslash outside of quotes escapes any char.
+fn split_words(s: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    // `None` between words, so that `''` still makes an empty one
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => return Err("unterminated single quote".into()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\')) => word.push(ch),
+                            Some(ch) => {
+                                word.push('\\');
+                                word.push(ch);
+                            }
+                            None => return Err("unterminated double quote".into()),
+                        },
+                        Some(ch) => word.push(ch),
+                        None => return Err("unterminated double quote".into()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(ch) => word.get_or_insert_default().push(ch),
+                None => return Err("trailing backslash".into()),
+            },
+            ch => word.get_or_insert_default().push(ch),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> {
+        split_words(s).unwrap()
+    }
+
+    #[test]
+    fn words_split_at_whitespace() {
+        assert_eq!(
+            words("  hx  {file}:{line}\t-v "),
+            ["hx", "{file}:{line}", "-v"]
+        );
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_words_together() {
+        assert_eq!(
+            words(r#"kak -e "edit {file} {line} {column}""#),
+            ["kak", "-e", "edit {file} {line} {column}"]
+        );
+        assert_eq!(words(r#"echo 'a "b" \c'"#), ["echo", r#"a "b" \c"#]);
+        // Quoted parts join the word around them, and empty quotes still make one
+        assert_eq!(
+            words(r#"--file='a b'"c d" '' """#),
+            ["--file=a bc d", "", ""]
+        );
+    }
+
+    #[test]
+    fn backslashes_escape() {
+        assert_eq!(words(r"a\ b c\\d"), ["a b", r"c\d"]);
+        // Inside double quotes only before `"` and `\`
+        assert_eq!(words(r#""\"\\\n""#), [r#""\\n"#]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert_eq!(
+            split_words("'open"),
+            Err("unterminated single quote".into())
+        );
+        assert_eq!(
+            split_words(r#""open"#),
+            Err("unterminated double quote".into())
+        );
+        assert_eq!(split_words(r"open\"), Err("trailing backslash".into()));
+        assert_eq!(
+            "".parse::<ExecTemplate>(),
+            Err("the command is empty".into())
+        );
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_after_splitting() {
+        let template: ExecTemplate = "kak -e 'edit {file} {line} {column}' {name}"
+            .parse()
+            .unwrap();
+        let r = Ref {
+            name: "parse".into(),
+            file: PathBuf::from("/my repo/src/lib.rs"),
+            line: 12,
+            column: 4,
+            ..Default::default()
+        };
+        let command = template.command(&r);
+        assert_eq!(command.get_program(), "kak");
+        // The space in the path doesn't split the argument, and columns count from 1
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-e", "edit /my repo/src/lib.rs 12 5", "parse"]);
+    }
+}
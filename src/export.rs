@@ -0,0 +1,29 @@
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use tourust::{IndexOptions, error::Result, find_refs};
+
+use crate::cli::{ExportArgs, ExportFormat};
+
+pub fn export(args: ExportArgs, root: &Path, opts: IndexOptions) -> Result<()> {
+    let refs = find_refs(root, opts)?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    match args.format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &refs)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Ndjson => {
+            for r in &refs {
+                serde_json::to_writer(&mut out, r)?;
+                writeln!(out)?;
+            }
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
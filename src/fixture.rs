@@ -0,0 +1,33 @@
+//! Temporary source trees for the tests, of both the library and the binary.
+
+use std::{fs, ops::Deref, path::Path};
+
+use tempfile::TempDir;
+
+/// A directory holding a few files for a test, removed once dropped, even by a panicking test.
+pub struct Fixture(TempDir);
+
+impl Deref for Fixture {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl AsRef<Path> for Fixture {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+
+/// A fresh directory with `files` written to it, each given by its path in the directory.
+pub fn fixture(files: &[(&str, &str)]) -> Fixture {
+    let dir = TempDir::with_prefix("tourust-").unwrap();
+    for (file, src) in files {
+        let path = dir.path().join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, src).unwrap();
+    }
+    Fixture(dir)
+}
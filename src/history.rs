@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tourust::{Ref, error::Result};
+
+/// How many selections are remembered per project.
+const MAX_ENTRIES: usize = 20;
+/// Score added to the most recent selection, older ones get proportionally less.
+const MAX_BOOST: i64 = 10;
+/// After this many seconds a visit counts half as much towards frecency.
+const HALF_LIFE: f64 = 60.0 * 60.0 * 24.0 * 3.0;
+/// Upper bound of the frecency boost, so it refines the fuzzy score without overriding it.
+const MAX_FRECENCY_BOOST: i64 = 30;
+/// How many refs' visits are kept per project, the least frecent are forgotten beyond that.
+const MAX_VISITED: usize = 500;
+/// Visits that decayed below this frecency no longer boost anything and are forgotten.
+const MIN_FRECENCY: f64 = 0.01;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub file: PathBuf,
+    pub sig: String,
+}
+
+impl From<&Ref> for Entry {
+    fn from(value: &Ref) -> Self {
+        Self {
+            file: value.file.to_owned(),
+            sig: value.sig.to_owned(),
+        }
+    }
+}
+
+/// How often and how lately a ref was selected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Visits {
+    pub count: u32,
+    /// Unix timestamp of the latest selection, in seconds
+    pub last: u64,
+}
+
+impl Visits {
+    /// Frequency decayed by recency: each visit is worth 1 when fresh and loses half its weight
+    /// every [`HALF_LIFE`].
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last) as f64;
+        self.count as f64 * 0.5f64.powf(age / HALF_LIFE)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Project {
+    /// Most recent first
+    recent: Vec<Entry>,
+    /// Keyed by file, then by signature
+    visits: HashMap<PathBuf, HashMap<String, Visits>>,
+}
+
+impl Project {
+    /// Forget the visits that no longer count for anything, and the least frecent ones beyond
+    /// [`MAX_VISITED`].
+    fn prune(&mut self, now: u64) {
+        let mut frecencies: Vec<f64> = self
+            .visits
+            .values()
+            .flat_map(HashMap::values)
+            .map(|visits| visits.frecency(now))
+            .collect();
+        let mut cutoff = MIN_FRECENCY;
+        if frecencies.len() > MAX_VISITED {
+            frecencies.sort_by(|a, b| b.total_cmp(a));
+            cutoff = cutoff.max(frecencies[MAX_VISITED - 1]);
+        }
+        for sigs in self.visits.values_mut() {
+            sigs.retain(|_, visits| visits.frecency(now) >= cutoff);
+        }
+        self.visits.retain(|_, sigs| !sigs.is_empty());
+    }
+}
+
+/// Recently selected refs, persisted across runs in the data dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// Selections per search root, so different projects don't mix
+    projects: HashMap<PathBuf, Project>,
+}
+
+pub struct History {
+    root: PathBuf,
+    state: State,
+    /// Whether there are selections that haven't been saved yet
+    dirty: bool,
+    /// Score added per unit of frecency
+    pub frecency_weight: f64,
+}
+
+/// Seconds since the Unix epoch, what [`History::boost`] measures recency against.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The boost a ref with these visits gets, proportional to its frecency but capped.
+pub fn frecency_boost(visits: &Visits, weight: f64, now: u64) -> i64 {
+    ((visits.frecency(now) * weight).round() as i64).clamp(0, MAX_FRECENCY_BOOST)
+}
+
+impl History {
+    fn state_file() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("tourust").join("history.json"))
+    }
+
+    /// Load the history for the project at `root`, starting over if there is none yet or the
+    /// state file can't be read.
+    pub fn load(root: &Path) -> Self {
+        let state = Self::state_file()
+            .filter(|file| file.exists())
+            .and_then(|file| {
+                fs::read_to_string(&file)
+                    .map_err(|err| warn!("Failed to read {}: {}", file.display(), err))
+                    .ok()
+            })
+            .and_then(|src| {
+                serde_json::from_str(&src)
+                    .map_err(|err| warn!("Ignoring malformed history: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        Self {
+            root: root.to_owned(),
+            state,
+            dirty: false,
+            frecency_weight: 2.0,
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(file) = Self::state_file() else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, serde_json::to_string(&self.state)?)?;
+        Ok(())
+    }
+
+    /// Save the history if anything was recorded since it was last saved, forgetting visits
+    /// that decayed or didn't make the cut.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.dirty {
+            let now = now();
+            for project in self.state.projects.values_mut() {
+                project.prune(now);
+            }
+            self.save()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Move `r` to the front of the history, forgetting the oldest entry if it is full, and
+    /// count the visit. It is only written out on [`History::flush`].
+    pub fn record(&mut self, r: &Ref) {
+        self.dirty = true;
+        let entry = Entry::from(r);
+        let project = self.state.projects.entry(self.root.to_owned()).or_default();
+
+        let visits = project
+            .visits
+            .entry(entry.file.clone())
+            .or_default()
+            .entry(entry.sig.clone())
+            .or_default();
+        visits.count += 1;
+        visits.last = now();
+
+        project.recent.retain(|e| *e != entry);
+        project.recent.insert(0, entry);
+        project.recent.truncate(MAX_ENTRIES);
+    }
+
+    /// Score bonus for a ref, larger the more recently and often it was selected before `now`
+    /// and 0 if it never was.
+    pub fn boost(&self, r: &Ref, now: u64) -> i64 {
+        let Some(project) = self.state.projects.get(&self.root) else {
+            return 0;
+        };
+
+        let recency = project
+            .recent
+            .iter()
+            .position(|e| e.file == r.file && e.sig == r.sig)
+            .map_or(0, |i| {
+                MAX_BOOST * (MAX_ENTRIES - i) as i64 / MAX_ENTRIES as i64
+            });
+        let frecency = project
+            .visits
+            .get(&r.file)
+            .and_then(|sigs| sigs.get(&r.sig))
+            .map_or(0, |visits| {
+                frecency_boost(visits, self.frecency_weight, now)
+            });
+        recency + frecency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 60 * 60 * 24;
+
+    fn history() -> History {
+        History {
+            root: PathBuf::from("/project"),
+            state: State::default(),
+            dirty: false,
+            frecency_weight: 2.0,
+        }
+    }
+
+    fn r(sig: &str) -> Ref {
+        Ref {
+            file: PathBuf::from("/project/src/lib.rs"),
+            sig: sig.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fresh_visits_count_fully() {
+        let visits = Visits {
+            count: 3,
+            last: 1000,
+        };
+        assert_eq!(visits.frecency(1000), 3.0);
+        // Visits from the future, e.g. after the clock was turned back, don't count extra
+        assert_eq!(visits.frecency(0), 3.0);
+    }
+
+    #[test]
+    fn visits_halve_every_half_life() {
+        let visits = Visits { count: 4, last: 0 };
+        let half_life = HALF_LIFE as u64;
+        assert!((visits.frecency(half_life) - 2.0).abs() < 1e-9);
+        assert!((visits.frecency(2 * half_life) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frequent_beats_rare_at_the_same_age() {
+        let now = 10 * DAY;
+        let often = Visits {
+            count: 10,
+            last: now - DAY,
+        };
+        let once = Visits {
+            count: 1,
+            last: now - DAY,
+        };
+        assert!(frecency_boost(&often, 2.0, now) > frecency_boost(&once, 2.0, now));
+    }
+
+    #[test]
+    fn boost_is_capped() {
+        let visits = Visits {
+            count: 1000,
+            last: 0,
+        };
+        assert_eq!(frecency_boost(&visits, 2.0, 0), MAX_FRECENCY_BOOST);
+        assert_eq!(frecency_boost(&visits, 0.0, 0), 0);
+    }
+
+    #[test]
+    fn recorded_refs_are_boosted() {
+        let mut history = history();
+        let (picked, other) = (r("fn picked()"), r("fn other()"));
+        history.record(&picked);
+        history.record(&picked);
+        let now = now();
+        assert_eq!(history.boost(&other, now), 0);
+        // Most recent selection plus two fresh visits
+        assert_eq!(history.boost(&picked, now), MAX_BOOST + 4);
+    }
+
+    #[test]
+    fn old_visits_are_pruned() {
+        let mut project = Project::default();
+        let now = 100 * DAY;
+        for (sig, last) in [("fn fresh()", now), ("fn stale()", 0)] {
+            project
+                .visits
+                .entry(PathBuf::from("lib.rs"))
+                .or_default()
+                .insert(sig.into(), Visits { count: 1, last });
+        }
+        project.prune(now);
+        let sigs: Vec<&String> = project.visits.values().flat_map(HashMap::keys).collect();
+        assert_eq!(sigs, ["fn fresh()"]);
+    }
+
+    #[test]
+    fn only_the_most_frecent_visits_are_kept() {
+        let mut project = Project::default();
+        let sigs = project.visits.entry(PathBuf::from("lib.rs")).or_default();
+        for i in 0..MAX_VISITED + 10 {
+            sigs.insert(
+                format!("fn f{i}()"),
+                Visits {
+                    count: i as u32 + 1,
+                    last: 0,
+                },
+            );
+        }
+        project.prune(0);
+        let kept = &project.visits[Path::new("lib.rs")];
+        assert_eq!(kept.len(), MAX_VISITED);
+        assert!(!kept.contains_key("fn f0()"));
+        assert!(kept.contains_key(&format!("fn f{}()", MAX_VISITED + 9)));
+    }
+}
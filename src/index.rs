@@ -0,0 +1,1190 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::BufRead,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{DirEntry, WalkBuilder};
+use log::warn;
+use syn::{
+    Attribute, Block, Fields, ImplItem, Item, ItemMacro, LitStr, ext::IdentExt, visit::Visit,
+};
+
+use crate::{
+    attrs::{HasAttrs, derives, is_doc_hidden, is_test},
+    error::{Error, Result},
+    refs::{IsRelevant, Ref, RefKind, foreign_ref},
+    target::{CargoTarget, Manifests},
+};
+
+/// Options controlling which files are discovered and how they are indexed.
+#[derive(Clone, Debug)]
+pub struct IndexOptions {
+    /// File extensions to discover, without the leading dot.
+    pub extensions: Vec<String>,
+    /// Whether hidden files and directories are searched.
+    pub hidden: bool,
+    /// Whether `#[cfg(test)]` modules and `#[test]` functions are indexed.
+    pub include_tests: bool,
+    /// Whether `#[doc(hidden)]` items are left out.
+    pub hide_doc_hidden: bool,
+    /// Whether named struct fields and enum variants are indexed as refs of their own.
+    pub fields: bool,
+    /// Whether the items of files included with `include!("..")` are indexed, at the position
+    /// of the `include!`.
+    pub follow_includes: bool,
+    /// Whether items declared inside function bodies are indexed, qualified by the function
+    /// like `outer::inner`. Every body has to be walked, so this is slower.
+    pub nested_items: bool,
+    /// Whether the functions, statics and types declared in `extern` blocks are indexed.
+    pub foreign_items: bool,
+    /// Whether the functions of `impl` blocks are indexed, without them only the blocks
+    /// themselves are, as long as [`RefKind::Impl`] is among the kinds.
+    pub impl_items: bool,
+    /// Files larger than this many bytes are skipped, typically generated code that is slow to
+    /// parse and not worth jumping into. 0 indexes files of any size.
+    pub max_file_size: u64,
+    /// How many directory levels below the search root are searched, 1 only discovers the files
+    /// directly in it. Unlimited if `None`.
+    pub max_depth: Option<usize>,
+    /// Files whose path relative to the search root matches are not indexed.
+    pub exclude: GlobSet,
+    /// Cargo targets whose files are indexed, all of them and files outside of packages if
+    /// empty.
+    pub targets: Vec<CargoTarget>,
+    /// Whether refs are given the Cargo target of their file, see [`Ref::target`]. Without it
+    /// manifests are only read to filter by [`IndexOptions::targets`].
+    pub cargo_targets: bool,
+    /// Kinds of items that get refs. Excluding a kind doesn't exclude what is nested inside
+    /// such items, e.g. the methods of an `impl`.
+    pub kinds: Vec<RefKind>,
+}
+
+/// Largest file indexed unless configured otherwise, far beyond anything written by hand.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+impl IndexOptions {
+    /// Imports are rarely what you want to jump to, so they aren't indexed unless asked for.
+    pub fn default_kinds() -> Vec<RefKind> {
+        RefKind::ALL
+            .into_iter()
+            .filter(|kind| *kind != RefKind::Use)
+            .collect()
+    }
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            extensions: vec!["rs".into()],
+            hidden: true,
+            include_tests: false,
+            hide_doc_hidden: false,
+            fields: false,
+            follow_includes: false,
+            nested_items: false,
+            foreign_items: false,
+            impl_items: true,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_depth: None,
+            targets: Vec::new(),
+            cargo_targets: true,
+            exclude: GlobSet::empty(),
+            kinds: Self::default_kinds(),
+        }
+    }
+}
+
+/// Compile glob patterns such as `**/generated/**` for [`IndexOptions::exclude`]. A `*` does
+/// not cross directory separators, use `**` for that.
+pub fn build_globs(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(builder.build()?)
+}
+
+/// How far an indexing run has come, in files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Indexes the files below a root one at a time, yielding each file's refs as soon as it has
+/// been parsed so callers can report [`Progress`] or stream results.
+pub struct Indexer {
+    root: PathBuf,
+    opts: IndexOptions,
+    files: Vec<PathBuf>,
+    done: usize,
+    manifests: Manifests,
+}
+
+impl Indexer {
+    /// Discover the files below `path`, parsing happens lazily while iterating.
+    pub fn new(path: &Path, opts: IndexOptions) -> Self {
+        Self {
+            root: path.to_owned(),
+            files: discover_files(path, &opts),
+            opts,
+            done: 0,
+            manifests: Manifests::default(),
+        }
+    }
+
+    /// Index the given files instead of discovering them, e.g. a list piped in from `fd`. The
+    /// module paths are still derived relative to `root`. Files that don't exist or don't have
+    /// one of the extensions are skipped with a warning.
+    pub fn with_files(root: &Path, files: Vec<PathBuf>, opts: IndexOptions) -> Self {
+        let files = files
+            .into_iter()
+            .filter(|file| {
+                if !file.is_file() {
+                    warn!("Skipping {}: not a file", file.display());
+                    return false;
+                }
+                if !has_extension(file, &opts) {
+                    warn!(
+                        "Skipping {}: not a {} file",
+                        file.display(),
+                        opts.extensions.join("/")
+                    );
+                    return false;
+                }
+                true
+            })
+            .filter(|file| !is_path_excluded(root, file, &opts))
+            .collect();
+        let files = dedup_files(files);
+        Self {
+            root: root.to_owned(),
+            files,
+            opts,
+            done: 0,
+            manifests: Manifests::default(),
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            done: self.done,
+            total: self.files.len(),
+        }
+    }
+}
+
+impl Iterator for Indexer {
+    type Item = (PathBuf, Result<Vec<Ref>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let file = self.files.get(self.done)?.to_owned();
+        self.done += 1;
+        let refs = index_file_with(&self.root, &file, &self.opts, &mut self.manifests);
+        Some((file, refs))
+    }
+}
+
+/// Parse every matching file below `path` and collect a [`Ref`] for each relevant item.
+pub fn find_refs(path: &Path, opts: IndexOptions) -> Result<Vec<Ref>> {
+    let mut refs = Vec::<Ref>::new();
+    for (_, file_refs) in Indexer::new(path, opts) {
+        refs.extend(file_refs?);
+    }
+
+    Ok(refs)
+}
+
+/// The refs of a whole tree, to look symbols up in without filtering them by hand.
+///
+/// ```
+/// use std::path::Path;
+///
+/// use tourust::{Index, IndexOptions, RefKind};
+///
+/// let index = Index::build(Path::new("src"), IndexOptions::default())?;
+/// let public_fns = index.query(|r| r.kind == RefKind::Fn && r.visibility.is_exported());
+/// assert!(public_fns.iter().any(|r| r.name == "find_refs"));
+/// # Ok::<(), tourust::error::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    refs: Vec<Ref>,
+}
+
+impl Index {
+    /// Index every matching file below `path`, like [`find_refs`].
+    pub fn build(path: &Path, opts: IndexOptions) -> Result<Self> {
+        Ok(Self::from(find_refs(path, opts)?))
+    }
+
+    pub fn refs(&self) -> &[Ref] {
+        &self.refs
+    }
+
+    /// The refs `predicate` holds for, in the order they were indexed.
+    pub fn query(&self, predicate: impl Fn(&Ref) -> bool) -> Vec<&Ref> {
+        self.refs.iter().filter(|r| predicate(r)).collect()
+    }
+
+    /// The refs of one kind, e.g. every trait.
+    pub fn by_kind(&self, kind: RefKind) -> Vec<&Ref> {
+        self.query(|r| r.kind == kind)
+    }
+
+    /// The refs whose name contains `needle`, ignoring ASCII case.
+    pub fn by_name_substr(&self, needle: &str) -> Vec<&Ref> {
+        let needle = needle.to_ascii_lowercase();
+        self.query(|r| r.name.to_ascii_lowercase().contains(&needle))
+    }
+}
+
+impl From<Vec<Ref>> for Index {
+    fn from(refs: Vec<Ref>) -> Self {
+        Self { refs }
+    }
+}
+
+/// Project-local exclusions in gitignore syntax, for files that are tracked but not worth
+/// indexing. Like `.gitignore` files these apply to their directory and below, and are also
+/// picked up from the parents of the search root.
+pub const IGNORE_FILE: &str = ".tourustignore";
+
+/// Find every file below `path` that should be indexed, honouring `.gitignore` and
+/// [`IGNORE_FILE`]s. Symlinks are followed, but symlink cycles are skipped and a file reachable
+/// through several paths is only returned once.
+pub fn discover_files(path: &Path, opts: &IndexOptions) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkBuilder::new(path)
+        .hidden(!opts.hidden)
+        .git_ignore(true)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .follow_links(true)
+        .max_depth(opts.max_depth)
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                warn!("Skipping unreadable entry: {}", err);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|ty| ty.is_file()))
+        .filter(|entry| has_extension(entry.path(), opts))
+        .map(DirEntry::into_path)
+        .collect();
+    files.retain(|file| !is_path_excluded(path, file, opts));
+    dedup_files(files)
+}
+
+/// Drop files that resolve to the same file as an earlier one, e.g. through a symlink. The
+/// first path seen is kept.
+fn dedup_files(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    files
+        .into_iter()
+        .filter(|file| seen.insert(fs::canonicalize(file).unwrap_or_else(|_| file.clone())))
+        .collect()
+}
+
+/// Read a newline separated list of files, e.g. the output of `fd -e rs`. Blank lines are
+/// ignored.
+pub fn read_file_list(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() {
+            files.push(PathBuf::from(line));
+        }
+    }
+    Ok(files)
+}
+
+/// The files with uncommitted changes below `root`, staged ones if `staged` is set, as listed
+/// by `git diff --name-only`. Deleted files are left out as there is nothing to index.
+pub fn git_changed_files(root: &Path, staged: bool) -> Result<Vec<PathBuf>> {
+    // Outside of a repository `git diff` compares paths instead and only prints its usage
+    git(root, &["rev-parse", "--show-toplevel"])?;
+    let mut args = vec!["diff", "--name-only", "--relative", "--diff-filter=d"];
+    if staged {
+        args.push("--staged");
+    }
+    let files = read_file_list(git(root, &args)?.as_slice())?;
+    Ok(files.into_iter().map(|file| root.join(file)).collect())
+}
+
+/// Run git in `dir` and return what it printed to stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(Error::Git(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn has_extension(file: &Path, opts: &IndexOptions) -> bool {
+    // Compare extensions as OS strings so files with non UTF-8 names are still found
+    file.extension()
+        .is_some_and(|ext| opts.extensions.iter().any(|e| ext == e.as_str()))
+}
+
+/// Whether a file matches the exclude globs, which are relative to the search root.
+fn is_path_excluded(root: &Path, file: &Path, opts: &IndexOptions) -> bool {
+    opts.exclude
+        .is_match(file.strip_prefix(root).unwrap_or(file))
+}
+
+/// Parse a single file discovered below `root` and collect a [`Ref`] for each relevant item.
+pub fn index_file(root: &Path, file: &Path, opts: &IndexOptions) -> Result<Vec<Ref>> {
+    index_file_with(root, file, opts, &mut Manifests::default())
+}
+
+/// [`index_file`], looking the file's Cargo target up in the `manifests` read so far.
+fn index_file_with(
+    root: &Path,
+    file: &Path,
+    opts: &IndexOptions,
+    manifests: &mut Manifests,
+) -> Result<Vec<Ref>> {
+    let target = if opts.cargo_targets || !opts.targets.is_empty() {
+        manifests.target_of(file)
+    } else {
+        None
+    };
+    if !opts.targets.is_empty() && !target.is_some_and(|target| opts.targets.contains(&target)) {
+        return Ok(Vec::new());
+    }
+    let size = fs::metadata(file)?.len();
+    if opts.max_file_size > 0 && size > opts.max_file_size {
+        warn!(
+            "Skipping {}: {} bytes is over the limit of {}",
+            file.display(),
+            size,
+            opts.max_file_size
+        );
+        return Ok(Vec::new());
+    }
+    let src = fs::read_to_string(file)?;
+    let syntax = syn::parse_file(&src)?;
+    let module_path = file_module_path(root, file);
+
+    let mut refs = Vec::<Ref>::new();
+    // Append refs with each item in the file that is relevant
+    for item in syntax.items {
+        recursive_find_refs(item, &mut refs, &[file], &module_path, opts)?;
+    }
+    // Included files are part of the target including them
+    for r in &mut refs {
+        r.target = target;
+    }
+
+    Ok(refs)
+}
+
+/// The file an `include!("..")` at item position pulls in, relative to the including file.
+/// Paths built by other macros, e.g. `concat!(env!("OUT_DIR"), ..)`, can't be resolved.
+fn included_file(mac: &ItemMacro, file: &Path) -> Option<PathBuf> {
+    if !mac.mac.path.is_ident("include") {
+        return None;
+    }
+    let path = mac.mac.parse_body::<LitStr>().ok()?.value();
+    Some(file.parent().unwrap_or(Path::new("")).join(path))
+}
+
+/// Index the items of a file included into the last of `files`, as if they were written
+/// there. Files already being included are skipped so include cycles end.
+fn index_included(
+    included: PathBuf,
+    refs: &mut Vec<Ref>,
+    files: &[&Path],
+    module_path: &[String],
+    opts: &IndexOptions,
+) -> Result<()> {
+    // Compare canonical paths, `gen/../lib.rs` is still `lib.rs`
+    let included = match fs::canonicalize(&included) {
+        Ok(included) => included,
+        Err(err) => {
+            warn!("Skipping included {}: {}", included.display(), err);
+            return Ok(());
+        }
+    };
+    if files
+        .iter()
+        .any(|file| fs::canonicalize(file).is_ok_and(|file| file == included))
+    {
+        warn!("Skipping {}: included in itself", included.display());
+        return Ok(());
+    }
+    let syntax = match fs::read_to_string(&included)
+        .map_err(Error::from)
+        .and_then(|src| Ok(syn::parse_file(&src)?))
+    {
+        Ok(syntax) => syntax,
+        // The including file is fine, only leave out what it pulls in
+        Err(err) => {
+            warn!("Skipping included {}: {}", included.display(), err);
+            return Ok(());
+        }
+    };
+    let mut files = files.to_vec();
+    files.push(&included);
+    for item in syntax.items {
+        recursive_find_refs(item, refs, &files, module_path, opts)?;
+    }
+    Ok(())
+}
+
+/// Derive the module path a file contributes from its location below the search root, e.g.
+/// `src/app/tui.rs` becomes `["app", "tui"]` and `src/lib.rs` becomes `[]`.
+fn file_module_path(root: &Path, file: &Path) -> Vec<String> {
+    let relative = file.strip_prefix(root).unwrap_or(file).with_extension("");
+    let mut components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    // Everything up to and including the source directory is crate layout, not modules
+    if let Some(src) = components.iter().rposition(|c| c == "src") {
+        components.drain(..=src);
+    }
+    if matches!(
+        components.last().map(String::as_str),
+        Some("mod" | "lib" | "main")
+    ) {
+        components.pop();
+    }
+    components
+}
+
+/// Whether the options leave out an item with these attributes.
+fn is_excluded(attrs: &[Attribute], opts: &IndexOptions) -> bool {
+    (!opts.include_tests && is_test(attrs)) || (opts.hide_doc_hidden && is_doc_hidden(attrs))
+}
+
+/// The items declared in a function body, in nested blocks and closures too, but not those
+/// inside these items, they are walked on their own.
+#[derive(Default)]
+struct NestedItems(Vec<Item>);
+
+impl<'ast> Visit<'ast> for NestedItems {
+    fn visit_item(&mut self, item: &'ast Item) {
+        self.0.push(item.clone());
+    }
+}
+
+/// Push the refs for the items declared in a function's `body`, under the function's `scope`
+/// within the module, e.g. `["Parser", "parse"]` for a method.
+fn find_nested_refs(
+    body: &Block,
+    scope: &[String],
+    refs: &mut Vec<Ref>,
+    files: &[&Path],
+    module_path: &[String],
+    opts: &IndexOptions,
+) -> Result<()> {
+    let mut nested = NestedItems::default();
+    nested.visit_block(body);
+    let module_path: Vec<String> = module_path.iter().chain(scope).cloned().collect();
+    for item in nested.0 {
+        recursive_find_refs(item, refs, files, &module_path, opts)?;
+    }
+    Ok(())
+}
+
+/// Push the refs for `item` and everything inside it. `files` are the file the item is in last,
+/// preceded by the files including it.
+fn recursive_find_refs(
+    item: Item,
+    refs: &mut Vec<Ref>,
+    files: &[&Path],
+    module_path: &[String],
+    opts: &IndexOptions,
+) -> Result<()> {
+    let file = *files.last().expect("the item's file");
+    // Skipping a module also skips everything inside it
+    if is_excluded(item.attrs(), opts) {
+        return Ok(());
+    }
+    // Push the item itself, followed by the impls derived for it. Only types can derive, syn
+    // still parses `#[derive(..)]` on anything else.
+    let derives = if opts.kinds.contains(&RefKind::Derive)
+        && matches!(item, Item::Struct(_) | Item::Enum(_) | Item::Union(_))
+    {
+        derives(item.attrs())
+    } else {
+        Vec::new()
+    };
+    if (item.is_relevant(opts) || !derives.is_empty())
+        && let Ok(mut r) = Ref::try_from((item.clone(), file.to_owned()))
+    {
+        r.module_path = module_path.to_vec();
+        let derived: Vec<Ref> = derives
+            .iter()
+            .map(|(derive, condition)| r.derived(derive, condition.as_deref()))
+            .collect();
+        if item.is_relevant(opts) {
+            refs.push(r);
+        }
+        refs.extend(derived);
+    }
+    match item {
+        Item::Mod(md) => {
+            // If the module has a body
+            if let Some(content) = md.content {
+                let mut module_path = module_path.to_vec();
+                module_path.push(md.ident.unraw().to_string());
+                // For every item in the module
+                for item in content.1 {
+                    recursive_find_refs(item, refs, files, &module_path, opts)?;
+                }
+            }
+        }
+        Item::Macro(mac) if opts.follow_includes => {
+            if let Some(included) = included_file(&mac, file) {
+                index_included(included, refs, files, module_path, opts)?;
+            }
+        }
+        Item::Impl(im)
+            if opts.impl_items
+                && (opts.kinds.contains(&RefKind::Fn) || opts.kinds.contains(&RefKind::Method)) =>
+        {
+            for impl_item in &im.items {
+                if let ImplItem::Fn(fun) = impl_item {
+                    if is_excluded(fun.attrs(), opts) {
+                        continue;
+                    }
+                    let mut r: Ref = (fun.clone(), &im, file.to_owned()).into();
+                    r.module_path = module_path.to_vec();
+                    let scope: Vec<String> = r.owner.iter().chain([&r.name]).cloned().collect();
+                    if opts.kinds.contains(&r.kind) {
+                        refs.push(r);
+                    }
+                    if opts.nested_items {
+                        find_nested_refs(&fun.block, &scope, refs, files, module_path, opts)?;
+                    }
+                }
+            }
+        }
+        Item::ForeignMod(foreign) if opts.foreign_items => {
+            for foreign_item in &foreign.items {
+                if is_excluded(foreign_item.attrs(), opts) {
+                    continue;
+                }
+                let Some(mut r) = foreign_ref(foreign_item, &foreign, file.to_owned()) else {
+                    continue;
+                };
+                if opts.kinds.contains(&r.kind) {
+                    r.module_path = module_path.to_vec();
+                    refs.push(r);
+                }
+            }
+        }
+        Item::Fn(fun) if opts.nested_items => {
+            let scope = [fun.sig.ident.unraw().to_string()];
+            find_nested_refs(&fun.block, &scope, refs, files, module_path, opts)?;
+        }
+        Item::Struct(strukt) if opts.fields => {
+            if let Fields::Named(fields) = &strukt.fields {
+                for field in &fields.named {
+                    if !opts.kinds.contains(&RefKind::Field) || is_excluded(&field.attrs, opts) {
+                        continue;
+                    }
+                    let mut r: Ref = (field.clone(), &strukt, file.to_owned()).into();
+                    r.module_path = module_path.to_vec();
+                    refs.push(r);
+                }
+            }
+        }
+        Item::Enum(enm) if opts.fields => {
+            for variant in &enm.variants {
+                if !opts.kinds.contains(&RefKind::Variant) || is_excluded(&variant.attrs, opts) {
+                    continue;
+                }
+                let mut r: Ref = (variant.clone(), &enm, file.to_owned()).into();
+                r.module_path = module_path.to_vec();
+                refs.push(r);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Visibility;
+    use crate::fixture::fixture;
+
+    /// The refs of `src` as if it were the crate root `lib.rs`.
+    fn refs_of(src: &str, opts: &IndexOptions) -> Vec<Ref> {
+        let mut refs = Vec::new();
+        for item in syn::parse_file(src).unwrap().items {
+            recursive_find_refs(item, &mut refs, &[Path::new("lib.rs")], &[], opts).unwrap();
+        }
+        refs
+    }
+
+    fn names(refs: &[Ref]) -> Vec<&str> {
+        refs.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    const WITH_TESTS: &str = "
+        pub fn real() {}
+
+        #[test]
+        fn stray_test() {}
+
+        #[cfg(test)]
+        mod tests {
+            fn helper() {}
+
+            #[test]
+            fn works() {}
+        }
+    ";
+
+    #[test]
+    fn test_items_are_excluded_by_default() {
+        let refs = refs_of(WITH_TESTS, &IndexOptions::default());
+        assert_eq!(names(&refs), ["real"]);
+    }
+
+    #[test]
+    fn test_items_are_included_on_request() {
+        let opts = IndexOptions {
+            include_tests: true,
+            ..Default::default()
+        };
+        let refs = refs_of(WITH_TESTS, &opts);
+        assert_eq!(
+            names(&refs),
+            ["real", "stray_test", "tests", "helper", "works"]
+        );
+    }
+
+    #[test]
+    fn doc_hidden_items_are_hidden_on_request() {
+        let src = "#[doc(hidden)] pub fn internal() {} pub fn api() {}";
+        assert_eq!(
+            names(&refs_of(src, &IndexOptions::default())),
+            ["internal", "api"]
+        );
+        let opts = IndexOptions {
+            hide_doc_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(names(&refs_of(src, &opts)), ["api"]);
+    }
+
+    const WITH_FIELDS: &str = "
+        pub struct Point {
+            pub x: u32,
+            y: Vec<String>,
+        }
+        pub struct Tuple(u8);
+        enum Shape {
+            Dot,
+            Circle(f64),
+        }
+    ";
+
+    #[test]
+    fn fields_and_variants_are_indexed_with_fields() {
+        let opts = IndexOptions {
+            fields: true,
+            ..Default::default()
+        };
+        let refs = refs_of(WITH_FIELDS, &opts);
+        let subitems: Vec<&Ref> = refs
+            .iter()
+            .filter(|r| matches!(r.kind, RefKind::Field | RefKind::Variant))
+            .collect();
+        assert_eq!(
+            subitems.iter().map(|r| r.sig.as_str()).collect::<Vec<_>>(),
+            [
+                "Point.x: u32",
+                "Point.y: Vec<String>",
+                "Shape::Dot",
+                "Shape::Circle"
+            ]
+        );
+        // Each points at its own line, not the item's
+        assert_eq!(
+            subitems.iter().map(|r| r.line).collect::<Vec<_>>(),
+            [3, 4, 8, 9]
+        );
+        assert_eq!(subitems[0].owner.as_deref(), Some("Point"));
+    }
+
+    #[test]
+    fn fields_and_variants_are_not_indexed_by_default() {
+        let refs = refs_of(WITH_FIELDS, &IndexOptions::default());
+        assert_eq!(names(&refs), ["Point", "Tuple", "Shape"]);
+    }
+
+    /// The names of everything indexed below `dir`, sorted.
+    fn indexed_names(dir: &Path, opts: IndexOptions) -> Vec<String> {
+        let mut names: Vec<String> = find_refs(dir, opts)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn excluded_files_are_not_indexed() {
+        let dir = fixture(&[
+            ("src/lib.rs", "fn kept() {}"),
+            ("src/generated/bindings.rs", "fn generated() {}"),
+            ("src/generated.rs", "fn sibling() {}"),
+        ]);
+        let opts = IndexOptions {
+            exclude: build_globs(&["src/generated/**".into()]).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(indexed_names(&dir, opts), ["kept", "sibling"]);
+    }
+
+    #[test]
+    fn exclude_globs_dont_cross_directories_with_a_single_star() {
+        let dir = fixture(&[("a.rs", "fn top() {}"), ("sub/b.rs", "fn nested() {}")]);
+        let opts = IndexOptions {
+            exclude: build_globs(&["*.rs".into()]).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(indexed_names(&dir, opts), ["nested"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_with_non_utf8_names_are_indexed() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = fixture(&[("lib.rs", "fn plain() {}")]);
+        let name = OsStr::from_bytes(b"caf\xe9.rs");
+        fs::write(dir.join(name), "fn latin1() {}").unwrap();
+        let refs = find_refs(&dir, IndexOptions::default()).unwrap();
+        let r = refs.iter().find(|r| r.name == "latin1").unwrap();
+        assert_eq!(r.file, dir.join(name));
+        // Lossy as a module, the path itself stays intact
+        assert_eq!(r.module_path, ["caf\u{fffd}"]);
+    }
+
+    #[test]
+    fn imports_are_only_indexed_when_their_kind_is() {
+        let src = "use std::collections::HashMap; fn f() {}";
+        assert_eq!(names(&refs_of(src, &IndexOptions::default())), ["f"]);
+        let opts = IndexOptions {
+            kinds: RefKind::ALL.to_vec(),
+            ..Default::default()
+        };
+        let refs = refs_of(src, &opts);
+        assert_eq!(refs[0].kind, RefKind::Use);
+        assert_eq!(refs.len(), 2);
+    }
+
+    #[test]
+    fn file_lists_skip_blank_lines_and_surrounding_space() {
+        let list = "src/a.rs\n\n  src/b.rs  \n\n";
+        assert_eq!(
+            read_file_list(list.as_bytes()).unwrap(),
+            [PathBuf::from("src/a.rs"), PathBuf::from("src/b.rs")]
+        );
+    }
+
+    #[test]
+    fn listed_files_are_indexed_skipping_missing_and_foreign_ones() {
+        let dir = fixture(&[
+            ("src/listed.rs", "fn listed() {}"),
+            ("src/unlisted.rs", "fn unlisted() {}"),
+            ("notes.txt", "fn not_rust() {}"),
+        ]);
+        let list = ["src/listed.rs", "src/missing.rs", "notes.txt"]
+            .map(|file| dir.join(file).display().to_string())
+            .join("\n");
+        let files = read_file_list(list.as_bytes()).unwrap();
+        let indexer = Indexer::with_files(&dir, files, IndexOptions::default());
+        assert_eq!(indexer.progress().total, 1);
+        let refs: Vec<Ref> = indexer.flat_map(|(_, refs)| refs.unwrap()).collect();
+        assert_eq!(names(&refs), ["listed"]);
+        // Module paths still come from the location below the root
+        assert_eq!(refs[0].module_path, ["listed"]);
+    }
+
+    #[test]
+    fn tourustignore_excludes_its_patterns() {
+        let dir = fixture(&[
+            (IGNORE_FILE, "vendor/\n*.gen.rs\n"),
+            ("src/lib.rs", "fn kept() {}"),
+            ("src/schema.gen.rs", "fn generated() {}"),
+            ("vendor/dep/lib.rs", "fn vendored() {}"),
+        ]);
+        assert_eq!(indexed_names(&dir, IndexOptions::default()), ["kept"]);
+    }
+
+    #[test]
+    fn tourustignore_above_the_root_applies_too() {
+        let dir = fixture(&[
+            (IGNORE_FILE, "generated/\n"),
+            ("crate/src/lib.rs", "fn kept() {}"),
+            ("crate/src/generated/mod.rs", "fn generated() {}"),
+        ]);
+        assert_eq!(
+            indexed_names(&dir.join("crate"), IndexOptions::default()),
+            ["kept"]
+        );
+    }
+
+    #[test]
+    fn derives_become_refs_at_the_deriving_type() {
+        let src = "
+            #[derive(Debug, Clone, serde::Serialize)]
+            pub struct Wire;
+        ";
+        let refs = refs_of(src, &IndexOptions::default());
+        let derived: Vec<&Ref> = refs.iter().filter(|r| r.kind == RefKind::Derive).collect();
+        assert_eq!(
+            derived.iter().map(|r| r.sig.as_str()).collect::<Vec<_>>(),
+            [
+                "derive Debug for Wire",
+                "derive Clone for Wire",
+                "derive serde::Serialize for Wire"
+            ]
+        );
+        assert!(derived.iter().all(|r| r.line == 3));
+        // The type itself comes first
+        assert_eq!(refs[0].name, "Wire");
+    }
+
+    #[test]
+    fn derives_can_be_left_out() {
+        let opts = IndexOptions {
+            kinds: vec![RefKind::Struct],
+            ..Default::default()
+        };
+        let refs = refs_of("#[derive(Clone)] struct S;", &opts);
+        assert_eq!(names(&refs), ["S"]);
+    }
+
+    #[test]
+    fn derives_on_non_types_are_ignored() {
+        // syn parses these, the compiler would reject them
+        let src = r#"
+            #[derive(Clone)]
+            extern "C" {}
+            #[derive(Clone)]
+            fn f() {}
+        "#;
+        let refs = refs_of(src, &IndexOptions::default());
+        assert_eq!(names(&refs), ["f"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_back_into_the_tree_dont_duplicate_files() {
+        use std::os::unix::fs::symlink;
+
+        let dir = fixture(&[
+            ("src/lib.rs", "fn once() {}"),
+            ("src/sub/a.rs", "fn also_once() {}"),
+        ]);
+        // A cycle, and a second path to the same file
+        symlink(&dir, dir.join("src/sub/loop")).unwrap();
+        symlink(dir.join("src/lib.rs"), dir.join("src/alias.rs")).unwrap();
+        let files = discover_files(&dir, &IndexOptions::default());
+        assert_eq!(files.len(), 2, "{files:?}");
+        assert_eq!(
+            indexed_names(&dir, IndexOptions::default()),
+            ["also_once", "once"]
+        );
+    }
+
+    #[test]
+    fn git_changed_files_lists_modified_files() {
+        let dir = fixture(&[
+            ("src/changed.rs", "fn before() {}"),
+            ("src/unchanged.rs", "fn same() {}"),
+            ("src/deleted.rs", "fn deleted() {}"),
+        ]);
+        for args in [
+            &["init", "-q"][..],
+            &["add", "."],
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "-qm",
+                "init",
+            ],
+        ] {
+            git(&dir, args).unwrap();
+        }
+        fs::write(dir.join("src/changed.rs"), "fn after() {}").unwrap();
+        fs::remove_file(dir.join("src/deleted.rs")).unwrap();
+        assert_eq!(
+            git_changed_files(&dir, false).unwrap(),
+            [dir.join("src/changed.rs")]
+        );
+        // Nothing is staged yet
+        assert!(git_changed_files(&dir, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn git_changed_files_fails_outside_a_repository() {
+        let dir = fixture(&[("lib.rs", "")]);
+        let err = git_changed_files(&dir, false).unwrap_err();
+        assert!(matches!(err, Error::Git(_)), "{err}");
+    }
+
+    const RAW_IDENTS: &str = "pub mod r#type {
+    pub fn r#match(r#in: u8) {}
+    pub struct r#async;
+}
+";
+
+    #[test]
+    fn raw_identifiers_are_named_without_their_prefix() {
+        let refs = refs_of(RAW_IDENTS, &IndexOptions::default());
+        assert_eq!(names(&refs), ["type", "match", "async"]);
+        let matcher = &refs[1];
+        assert_eq!(matcher.sig, "pub fn r#match(r#in: u8)");
+        assert_eq!(matcher.display_sig(), "pub fn match(in: u8)");
+        assert_eq!(matcher.match_sig, "fn match(in: u8)");
+        assert_eq!(matcher.path(), "crate::type::match");
+        // The cursor still lands on the item as written in the source
+        assert_eq!((matcher.line, matcher.column), (2, 8));
+    }
+
+    #[test]
+    fn included_files_are_indexed_in_place_on_request() {
+        let dir = fixture(&[
+            (
+                "src/lib.rs",
+                "mod sys { include!(\"bindings.in\"); }\ninclude!(\"missing.in\");\n",
+            ),
+            (
+                "src/bindings.in",
+                "pub fn generated() {}\ninclude!(\"bindings.in\");\n",
+            ),
+        ]);
+        let names = indexed_names(&dir, IndexOptions::default());
+        assert!(!names.contains(&"generated".to_string()), "{names:?}");
+        let opts = IndexOptions {
+            follow_includes: true,
+            ..Default::default()
+        };
+        // The cycle and the missing file are skipped, the include still indexed once
+        let refs = find_refs(&dir, opts).unwrap();
+        let generated: Vec<_> = refs.iter().filter(|r| r.name == "generated").collect();
+        assert_eq!(generated.len(), 1);
+        assert_eq!(generated[0].path(), "crate::sys::generated");
+        assert!(generated[0].file.ends_with("src/bindings.in"));
+    }
+
+    const NESTED: &str = "
+        fn outer() {
+            fn inner() {
+                fn innermost() {}
+            }
+            let parse = || {
+                struct Token;
+            };
+        }
+
+        impl Parser {
+            fn parse(&self) {
+                fn helper() {}
+            }
+        }
+    ";
+
+    #[test]
+    fn nested_items_are_left_out_by_default() {
+        let refs = refs_of(NESTED, &IndexOptions::default());
+        assert_eq!(names(&refs), ["outer", "Parser", "parse"]);
+    }
+
+    #[test]
+    fn nested_items_are_qualified_by_their_function() {
+        let opts = IndexOptions {
+            nested_items: true,
+            ..Default::default()
+        };
+        let refs = refs_of(NESTED, &opts);
+        let paths: Vec<String> = refs
+            .iter()
+            .filter(|r| r.kind != RefKind::Impl)
+            .map(Ref::path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "crate::outer",
+                "crate::outer::inner",
+                "crate::outer::inner::innermost",
+                "crate::outer::Token",
+                "crate::Parser::parse",
+                "crate::Parser::parse::helper",
+            ]
+        );
+    }
+
+    #[test]
+    fn files_over_the_size_limit_are_skipped() {
+        let generated = format!("pub fn huge() {{}} {}", "const X: u8 = 0; ".repeat(1000));
+        let dir = fixture(&[
+            ("src/lib.rs", "fn small() {}"),
+            ("src/generated.rs", &generated),
+        ]);
+        let opts = |max_file_size| IndexOptions {
+            kinds: vec![RefKind::Fn],
+            max_file_size,
+            ..Default::default()
+        };
+        assert_eq!(indexed_names(&dir, opts(1024)), ["small"]);
+        // No limit at all with 0
+        assert_eq!(indexed_names(&dir, opts(0)), ["huge", "small"]);
+    }
+
+    const FOREIGN: &str = r#"
+        extern "C" {
+            pub fn abs(x: i32) -> i32;
+            static mut errno: i32;
+            type FILE;
+        }
+    "#;
+
+    #[test]
+    fn foreign_items_are_left_out_by_default() {
+        let refs = refs_of(FOREIGN, &IndexOptions::default());
+        assert!(refs.is_empty(), "{refs:?}");
+    }
+
+    #[test]
+    fn foreign_items_show_their_abi() {
+        let opts = IndexOptions {
+            foreign_items: true,
+            ..Default::default()
+        };
+        let refs = refs_of(FOREIGN, &opts);
+        let found: Vec<_> = refs.iter().map(|r| (r.kind, r.sig.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (RefKind::Fn, r#"pub extern "C" fn abs(x: i32) -> i32"#),
+                (RefKind::Static, r#"extern "C" static mut errno: i32"#),
+                (RefKind::Type, r#"extern "C" type FILE"#),
+            ]
+        );
+        assert_eq!((refs[0].line, refs[0].visibility), (3, Visibility::Public));
+    }
+
+    #[test]
+    fn crlf_files_have_the_same_positions() {
+        let src = "// ünïcode\nmod m {\n\t/// Docs\n\tpub fn f() {\n\t}\n}\n";
+        let dir = fixture(&[
+            ("src/lf.rs", src),
+            ("src/crlf.rs", &src.replace('\n', "\r\n")),
+        ]);
+        for file in ["lf.rs", "crlf.rs"] {
+            let refs =
+                index_file(&dir, &dir.join("src").join(file), &IndexOptions::default()).unwrap();
+            let f = refs.iter().find(|r| r.name == "f").unwrap();
+            // Tabs count as one column like any char, as they do for editors
+            assert_eq!((f.line, f.column, f.end_line), (4, 5, 5), "{file}");
+            assert_eq!(f.byte_column(), 5, "{file}");
+            assert_eq!(f.docs.as_deref(), Some("Docs"), "{file}");
+            assert_eq!(f.source().unwrap(), "\tpub fn f() {\n\t}", "{file}");
+        }
+    }
+
+    #[test]
+    fn deeper_files_are_skipped_past_the_max_depth() {
+        let dir = fixture(&[
+            ("top.rs", "fn top() {}"),
+            ("a/one.rs", "fn one() {}"),
+            ("a/b/two.rs", "fn two() {}"),
+        ]);
+        let opts = |max_depth| IndexOptions {
+            max_depth,
+            ..Default::default()
+        };
+        assert_eq!(indexed_names(&dir, opts(Some(1))), ["top"]);
+        assert_eq!(indexed_names(&dir, opts(Some(2))), ["one", "top"]);
+        assert_eq!(indexed_names(&dir, opts(None)), ["one", "top", "two"]);
+    }
+
+    #[test]
+    fn cfg_attr_leaves_visibility_and_derives_intact() {
+        let opts = IndexOptions {
+            kinds: vec![RefKind::Struct, RefKind::Derive],
+            ..Default::default()
+        };
+        let refs = refs_of(
+            r#"#[cfg_attr(feature = "serde", derive(Serialize), allow(dead_code))]
+            pub struct Wire;"#,
+            &opts,
+        );
+        let [wire, derive] = refs.as_slice() else {
+            panic!("expected the struct and its derive, got {refs:?}");
+        };
+        assert_eq!(
+            (wire.kind, wire.visibility),
+            (RefKind::Struct, Visibility::Public)
+        );
+        assert_eq!(wire.cfg, None);
+        assert_eq!(derive.kind, RefKind::Derive);
+        assert_eq!(derive.cfg.as_deref(), Some(r#"feature = "serde""#));
+    }
+
+    const IMPL: &str = "struct S; impl S { fn new() -> Self { S } fn get(&self) {} }";
+
+    #[test]
+    fn impl_blocks_and_their_items_toggle_separately() {
+        let kinds = |opts: &IndexOptions| {
+            let refs = refs_of(IMPL, opts);
+            refs.iter()
+                .map(|r| (r.kind, r.name.clone()))
+                .collect::<Vec<_>>()
+        };
+        let both = IndexOptions::default();
+        assert_eq!(
+            kinds(&both),
+            [
+                (RefKind::Struct, "S".into()),
+                (RefKind::Impl, "S".into()),
+                (RefKind::Fn, "new".into()),
+                (RefKind::Method, "get".into()),
+            ]
+        );
+        let mut no_blocks = IndexOptions::default();
+        no_blocks.kinds.retain(|kind| *kind != RefKind::Impl);
+        assert_eq!(
+            kinds(&no_blocks),
+            [
+                (RefKind::Struct, "S".into()),
+                (RefKind::Fn, "new".into()),
+                (RefKind::Method, "get".into()),
+            ]
+        );
+        let no_items = IndexOptions {
+            impl_items: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            kinds(&no_items),
+            [(RefKind::Struct, "S".into()), (RefKind::Impl, "S".into())]
+        );
+    }
+}
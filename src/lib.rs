@@ -0,0 +1,31 @@
+//! Symbol indexing for Rust source trees, built on `syn`.
+//!
+//! Every relevant item in every discovered file becomes a [`Ref`] carrying its location,
+//! a display signature and its [`RefKind`].
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use tourust::{IndexOptions, find_refs};
+//!
+//! let refs = find_refs(Path::new("src"), IndexOptions::default())?;
+//! for r in &refs {
+//!     println!("{}:{} {}", r.file.display(), r.line, r.sig);
+//! }
+//! # Ok::<(), tourust::error::Error>(())
+//! ```
+
+mod attrs;
+pub mod error;
+#[cfg(test)]
+mod fixture;
+mod index;
+mod refs;
+mod target;
+
+pub use index::{
+    DEFAULT_MAX_FILE_SIZE, Index, IndexOptions, Indexer, Progress, build_globs, discover_files,
+    find_refs, git_changed_files, index_file, read_file_list,
+};
+pub use refs::{IsRelevant, ItemDisplay, Ref, RefKind, Visibility};
+pub use target::CargoTarget;
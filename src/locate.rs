@@ -0,0 +1,78 @@
+use std::{env, path::Path};
+
+use tourust::{
+    IndexOptions, Ref, RefKind,
+    error::{Error, Result},
+    index_file,
+};
+
+use crate::{cli::LocateArgs, open};
+
+/// The ref spanning the fewest lines of those spanning `line`.
+fn innermost(refs: Vec<Ref>, line: usize) -> Option<Ref> {
+    // Derived impls share the span of their item, prefer the item itself
+    refs.into_iter()
+        .filter(|r| (r.line..=r.end_line).contains(&line))
+        .min_by_key(|r| (r.line_count(), r.kind == RefKind::Derive))
+}
+
+/// Find the innermost ref whose item spans the given line, e.g. the method rather than its
+/// `impl`, and print it as `file:line:column: path` relative to `root` or open its file.
+pub fn locate(args: LocateArgs, root: &Path, opts: IndexOptions) -> Result<()> {
+    let file = env::current_dir()?.join(&args.location.file);
+    let line = args.location.line;
+    let refs = index_file(root, &file, &opts)?;
+
+    let r =
+        innermost(refs, line).ok_or_else(|| Error::NoSymbolAt(args.location.file.clone(), line))?;
+    if args.open {
+        return open::open_ref(&r);
+    }
+    // Columns count from 1 like in compiler messages, ours from 0
+    let file = r.file.strip_prefix(root).unwrap_or(&r.file);
+    println!(
+        "{}:{}:{}: {}",
+        file.display(),
+        r.line,
+        r.column + 1,
+        r.path()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::fixture;
+
+    const SHAPES: &str = "#[derive(Debug)]
+struct Shape;
+
+impl Shape {
+    fn area(&self) -> u32 {
+        0
+    }
+}
+";
+
+    fn located(line: usize) -> Option<(RefKind, String)> {
+        let dir = fixture(&[("lib.rs", SHAPES)]);
+        let refs = index_file(&dir, &dir.join("lib.rs"), &IndexOptions::default()).unwrap();
+        innermost(refs, line).map(|r| (r.kind, r.name))
+    }
+
+    #[test]
+    fn the_innermost_item_is_located() {
+        // The method rather than its impl
+        assert_eq!(located(6), Some((RefKind::Method, "area".into())));
+        assert_eq!(located(4), Some((RefKind::Impl, "Shape".into())));
+        // The struct rather than the impl derived for it
+        assert_eq!(located(2), Some((RefKind::Struct, "Shape".into())));
+    }
+
+    #[test]
+    fn lines_outside_any_item_locate_nothing() {
+        assert_eq!(located(3), None);
+        assert_eq!(located(100), None);
+    }
+}
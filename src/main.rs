@@ -1,38 +1,179 @@
+use std::{io, process::ExitCode};
+
 use app::App;
-use clap::Parser;
+use clap::{CommandFactory, Parser, error::ErrorKind};
 use cli::Cli;
-use error::Result;
+use config::Config;
+use flexi_logger::{FileSpec, Logger, LoggerHandle};
+use theme::Theme;
+use tourust::{error::Result, git_changed_files, read_file_list};
 
 mod app;
+mod capabilities;
+mod check;
 mod cli;
-mod error;
+mod config;
+mod exec;
+mod export;
+#[cfg(test)]
+mod fixture;
+mod history;
+mod locate;
 mod nvim;
+mod open;
+mod query;
+mod tags;
+mod theme;
 mod tui;
 
+/// Log to a file if `--log-level` or `--log-file` asks for it, otherwise stay silent.
+fn start_logger(cli: &Cli) -> Result<Option<LoggerHandle>> {
+    if cli.log_level.is_none() && cli.log_file.is_none() {
+        return Ok(None);
+    }
+    let level = cli.log_level.as_deref().unwrap_or("info");
+    let file_spec = match &cli.log_file {
+        Some(file) => FileSpec::try_from(file)?,
+        None => FileSpec::default(),
+    };
+    let handle = Logger::try_with_str(level)?
+        .log_to_file(file_spec)
+        .start()?;
+    Ok(Some(handle))
+}
+
+/// Exit status when the picker is closed without selecting anything, as for fzf.
+const EXIT_CANCELLED: u8 = 130;
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    //let _logger_handle = flexi_logger::Logger::try_with_str("debug")?
-    //    .log_to_file(FileSpec::default())
-    //    .start()?;
+async fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
+    // Logs go to a file as the TUI owns the terminal, kept alive until exit
+    let _logger_handle = start_logger(&cli)?;
+    let config = Config::load()?;
 
-    // create app and run it
-    let mut app = App::new()?;
-    if let Some(cmd) = cli.command {
-        match cmd {
-            cli::Command::Nvim(args) => {
-                app.select_callback = Some(Box::new(move |x| {
-                    nvim::select_callback(args.socket.clone(), x)
-                }));
-            }
+    let matcher = cli.matcher.or(config.matcher).unwrap_or_default();
+    if cli.capabilities {
+        capabilities::capabilities(&cli.index.options(&config)?, matcher)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let root = cli.root()?;
+    // The subcommands without a picker always index the whole root
+    if let Some(command) = cli.command.as_ref().filter(|command| command.is_batch())
+        && let Some(flag) = [(cli.stdin, "--stdin"), (cli.git_changed, "--git-changed")]
+            .into_iter()
+            .find_map(|(set, flag)| set.then_some(flag))
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "{flag} can't be used with the {} subcommand",
+                    command.name()
+                ),
+            )
+            .exit();
+    }
+    let mut opts = cli.index.options(&config)?;
+    // Only the picker's badges and the export show which target a ref is in
+    opts.cargo_targets = !matches!(
+        cli.command,
+        Some(cli::Command::Tags(_) | cli::Command::Check(_) | cli::Command::Locate(_))
+    );
+    match cli.command {
+        Some(cli::Command::Export(args)) => {
+            export::export(args, &root, opts)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(cli::Command::Tags(args)) => {
+            tags::tags(args, &root, opts)?;
+            return Ok(ExitCode::SUCCESS);
         }
+        Some(cli::Command::Check(args)) => {
+            check::check(args, &root, opts)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(cli::Command::Locate(args)) => {
+            locate::locate(args, &root, opts)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(cli::Command::Nvim(_)) | None => {}
+    }
+
+    // create app and run it
+    let mut builder = App::builder()
+        .options(opts)
+        .matcher(matcher)
+        .query(cli.query.clone().unwrap_or_default())
+        .root(&root);
+    if cli.stdin {
+        builder = builder.files(read_file_list(io::stdin().lock())?);
     }
+    if cli.git_changed {
+        builder = builder.files(git_changed_files(&root, cli.staged)?);
+    }
+    let mut app = match cli.command {
+        Some(cli::Command::Nvim(args)) => {
+            let mut app = builder.build()?;
+            let target = args.target();
+            let window = args.window;
+            app.select_callback = Some(Box::new({
+                let target = target.clone();
+                move |x| nvim::select_callback(target.clone(), window, x)
+            }));
+            app.quickfix_callback = Some(Box::new({
+                let target = target.clone();
+                move |x| nvim::quickfix_callback(target.clone(), x)
+            }));
+            app.scratch_callback = Some(Box::new(move |x| {
+                nvim::scratch_callback(target.clone(), window, x)
+            }));
+            app
+        }
+        _ => builder.build()?,
+    };
+    app.public_only = cli.public_only;
+    app.sort = cli.sort;
+    app.min_score = cli.min_score;
+    app.position_tie_break =
+        !cli.no_position_tie_break && config.position_tie_break.unwrap_or(true);
+    app.max_results = cli.max_results;
+    app.history.frecency_weight = cli.frecency_weight;
+    app.context = cli.context.or(config.context);
+    app.preview_highlight = !cli.no_preview_highlight;
+    app.preview_wrap = cli.wrap || config.wrap;
+    app.by_kind = cli.by_kind;
+    app.inline = cli.inline;
+    app.theme = Theme::from_config(&config.theme);
+    app.kind_weights.extend(config.kind_weights);
+    app.link_template = config.link_template;
+    app.enter_action = config.keys.enter.unwrap_or_default();
+    app.alt_enter_action = config.keys.alt_enter.unwrap_or_default();
 
-    match app.run().await {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            log::error!("Error encountered: {}", err.to_string());
-            Err(err)
+    let result = if cli.open_first {
+        app.run_first().await
+    } else {
+        app.run().await
+    };
+    if let Err(err) = &result {
+        log::error!("Error encountered: {}", err.to_string());
+    }
+    // However the app was left, keep what it learned
+    app.shutdown();
+    for r in &app.printed {
+        println!("{}", app::location_line(r, app.root()));
+    }
+    match result {
+        Ok(Some(selection)) => {
+            // The terminal is restored by now, so the command may take it over
+            if let Some(exec) = &cli.exec {
+                exec.run(&selection)?;
+            }
+            Ok(ExitCode::SUCCESS)
         }
+        // Let wrapping scripts tell a pick from a cancellation
+        Ok(None) => Ok(ExitCode::from(EXIT_CANCELLED)),
+        Err(err) => Err(err),
     }
 }
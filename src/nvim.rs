@@ -1,14 +1,26 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use log::{debug, error};
-use nvim_rs::{Buffer, Neovim, Value, Window, compat::tokio::Compat};
-use tokio::{io::WriteHalf, net::UnixStream};
+use futures::AsyncWrite;
+use log::{debug, error, warn};
+use nvim_rs::{Buffer, Neovim, Value, Window};
 
-use crate::{
-    app::Ref,
+use tourust::{
+    Ref,
     error::{Error, Result},
 };
 
+/// Where the neovim instance to jump in is listening.
+#[derive(Clone, Debug)]
+pub enum Target {
+    Socket(PathBuf),
+    /// A `host:port` address
+    Tcp(String),
+}
+
 #[derive(Clone)]
 struct NvimHandler {}
 
@@ -16,44 +28,202 @@ impl nvim_rs::Handler for NvimHandler {
     type Writer = nvim_rs::compat::tokio::Compat<tokio::io::WriteHalf<tokio::net::UnixStream>>;
 }
 
-pub async fn select_callback(socket: PathBuf, selection: Ref) -> Result<()> {
-    let handler = NvimHandler {};
+/// The same as `NvimHandler`, for an instance reached over TCP.
+#[derive(Clone)]
+struct TcpHandler {}
+
+impl nvim_rs::Handler for TcpHandler {
+    type Writer = nvim_rs::compat::tokio::Compat<tokio::io::WriteHalf<tokio::net::TcpStream>>;
+}
+
+/// Open every selected ref in the text window in order, leaving the last one shown and the
+/// others in listed buffers. The text window is `window` if given, see [`find_text_win`].
+pub async fn select_callback(
+    target: Target,
+    window: Option<i64>,
+    selection: Vec<Ref>,
+) -> Result<()> {
     debug!("selection: {:?}", selection);
 
     // Get our API
-    let (nvim, _) = nvim_rs::create::tokio::new_path(socket, handler)
-        .await
-        .unwrap_or_else(|err| {
-            println!("Error occured: {}", err);
-            panic!()
-        });
+    match target {
+        Target::Socket(socket) => {
+            let nvim = connect(&socket.display().to_string(), || async {
+                nvim_rs::create::tokio::new_path(&socket, NvimHandler {}).await
+            })
+            .await?;
+            open_selection(&nvim, window, &selection).await
+        }
+        Target::Tcp(addr) => {
+            let nvim = connect(&addr, || async {
+                nvim_rs::create::tokio::new_tcp(addr.as_str(), TcpHandler {}).await
+            })
+            .await?;
+            open_selection(&nvim, window, &selection).await
+        }
+    }
+}
+
+/// Replace the quickfix list with `refs` and open the quickfix window, to step through them
+/// with `:cnext`.
+pub async fn quickfix_callback(target: Target, refs: Vec<Ref>) -> Result<()> {
+    match target {
+        Target::Socket(socket) => {
+            let nvim = connect(&socket.display().to_string(), || async {
+                nvim_rs::create::tokio::new_path(&socket, NvimHandler {}).await
+            })
+            .await?;
+            fill_quickfix(&nvim, &refs).await
+        }
+        Target::Tcp(addr) => {
+            let nvim = connect(&addr, || async {
+                nvim_rs::create::tokio::new_tcp(addr.as_str(), TcpHandler {}).await
+            })
+            .await?;
+            fill_quickfix(&nvim, &refs).await
+        }
+    }
+}
+
+async fn fill_quickfix<W>(nvim: &Neovim<W>, refs: &[Ref]) -> Result<()>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let mut entries = Vec::new();
+    for r in refs {
+        let name = r
+            .file
+            .to_str()
+            .ok_or_else(|| Error::Utf8(r.file.to_owned()))?;
+        entries.push(Value::Map(vec![
+            ("filename".into(), name.into()),
+            ("lnum".into(), (r.line as i64).into()),
+            // Quickfix columns count bytes from 1
+            ("col".into(), (r.byte_column() as i64 + 1).into()),
+            ("text".into(), r.sig.as_str().into()),
+        ]));
+    }
+    nvim.call_function("setqflist", vec![Value::Array(entries), " ".into()])
+        .await?;
+    nvim.command("copen").await?;
+    Ok(())
+}
+
+/// Copy the whole item of each ref into a new scratch buffer named after it, showing the last
+/// one in the text window.
+pub async fn scratch_callback(target: Target, window: Option<i64>, refs: Vec<Ref>) -> Result<()> {
+    match target {
+        Target::Socket(socket) => {
+            let nvim = connect(&socket.display().to_string(), || async {
+                nvim_rs::create::tokio::new_path(&socket, NvimHandler {}).await
+            })
+            .await?;
+            open_scratch(&nvim, window, &refs).await
+        }
+        Target::Tcp(addr) => {
+            let nvim = connect(&addr, || async {
+                nvim_rs::create::tokio::new_tcp(addr.as_str(), TcpHandler {}).await
+            })
+            .await?;
+            open_scratch(&nvim, window, &refs).await
+        }
+    }
+}
+
+/// How many numbered names to try for a scratch buffer when its symbol already has one.
+const SCRATCH_NAME_ATTEMPTS: usize = 10;
+
+async fn open_scratch<W>(nvim: &Neovim<W>, window: Option<i64>, refs: &[Ref]) -> Result<()>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let win = find_text_win(nvim, window).await?;
+    for r in refs {
+        let source = r.source()?;
+        // A scratch buffer is `nofile`, so it never gets written anywhere
+        let buf = nvim.create_buf(true, true).await?;
+        buf.set_lines(0, -1, false, source.lines().map(str::to_owned).collect())
+            .await?;
+        buf.set_option("filetype", Value::from("rust")).await?;
+        // Buffer names are unique, e.g. when the same item is copied twice
+        let path = r.path();
+        for attempt in 1..=SCRATCH_NAME_ATTEMPTS {
+            let name = match attempt {
+                1 => path.clone(),
+                n => format!("{path} ({n})"),
+            };
+            if buf.set_name(&name).await.is_ok() {
+                break;
+            }
+        }
+        win.set_buf(&buf).await?;
+    }
+    Ok(())
+}
 
+async fn open_selection<W>(nvim: &Neovim<W>, window: Option<i64>, selection: &[Ref]) -> Result<()>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
     // Hide our application window
     //let self_win = nvim.get_current_win().await?;
     //self_win.close(false).await?;
 
-    let buf = find_or_open_buf(&nvim, &selection.file).await?;
-    let win = find_text_win(&nvim).await?;
-    win.set_buf(&buf).await?;
-    //nvim.set_current_buf(&buf).await?;
+    let win = find_text_win(nvim, window).await?;
+    for r in selection {
+        let buf = find_or_open_buf(nvim, &r.file).await?;
+        win.set_buf(&buf).await?;
+        //nvim.set_current_buf(&buf).await?;
 
-    if let Err(err) = win
-        .set_cursor((selection.line as i64, selection.column as i64))
-        .await
-    {
-        error!("Failed to set cursor: {}", err.to_string());
-        panic!()
+        // The line may be gone if the file was edited since it was indexed
+        win.set_cursor((r.line as i64, r.byte_column() as i64))
+            .await
+            .inspect_err(|err| error!("Failed to set cursor: {err}"))?;
     }
     Ok(())
 }
 
-async fn find_or_open_buf(
-    nvim: &Neovim<Compat<WriteHalf<UnixStream>>>,
-    file: &Path,
-) -> Result<Buffer<Compat<WriteHalf<UnixStream>>>> {
+/// How often to try reaching neovim before giving up.
+const CONNECT_ATTEMPTS: u32 = 4;
+/// Wait before the first retry, doubled after every failed attempt.
+const CONNECT_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Connect to neovim at `addr`, retrying with a short backoff since it may be briefly
+/// unavailable, e.g. while neovim is still starting up.
+async fn connect<W, J, F, Fut>(addr: &str, attempt_connect: F) -> Result<Neovim<W>>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+    F: Fn() -> Fut,
+    Fut: Future<Output = io::Result<(Neovim<W>, J)>>,
+{
+    let mut backoff = CONNECT_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match attempt_connect().await {
+            Ok((nvim, _)) => return Ok(nvim),
+            Err(err) if attempt >= CONNECT_ATTEMPTS => {
+                return Err(Error::Connect(addr.to_owned(), err));
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to connect to {} (attempt {}/{}): {}",
+                    addr, attempt, CONNECT_ATTEMPTS, err
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+async fn find_or_open_buf<W>(nvim: &Neovim<W>, file: &Path) -> Result<Buffer<W>>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
     // Look for and return previous buffer if it matches
     for buf in nvim.list_bufs().await? {
-        if buf.get_name().await? == file.to_str().ok_or(Error::Utf8)? {
+        if Path::new(&buf.get_name().await?) == file {
             buf.set_option("buflisted", Value::Boolean(true)).await?;
             return Ok(buf);
         }
@@ -63,20 +233,62 @@ async fn find_or_open_buf(
     let prev_buf = nvim.get_current_buf().await?;
     let buf = nvim.create_buf(true, false).await?;
     nvim.set_current_buf(&buf).await?;
-    nvim.command(format!("edit {}", file.display()).as_str())
-        .await?;
+    // Neovim only takes UTF-8 strings, a lossy name would open a different file
+    let name = file.to_str().ok_or_else(|| Error::Utf8(file.to_owned()))?;
+    // Spaces, `%`, `#` and the like mean something to `:edit`
+    let escaped = nvim.call_function("fnameescape", vec![name.into()]).await?;
+    let escaped = escaped
+        .as_str()
+        .ok_or_else(|| Error::Utf8(file.to_owned()))?;
+    nvim.command(format!("edit {}", escaped).as_str()).await?;
     nvim.set_current_buf(&prev_buf).await?;
     Ok(buf)
 }
 
-async fn find_text_win(
-    nvim: &Neovim<Compat<WriteHalf<UnixStream>>>,
-) -> Result<Window<Compat<WriteHalf<UnixStream>>>> {
+/// The window to open refs in: the window with id `forced` if given, otherwise the window the
+/// user worked in last. That is the current window, unless it is the terminal tourust runs in,
+/// then the previous one. Failing both, the first window showing a normal buffer.
+async fn find_text_win<W>(nvim: &Neovim<W>, forced: Option<i64>) -> Result<Window<W>>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    // Window parameters of the API take plain ids as well
+    if let Some(id) = forced {
+        let win = Window::new(Value::from(id), nvim.clone());
+        if !win.is_valid().await? {
+            return Err(Error::NoWindow);
+        }
+        return Ok(win);
+    }
+
+    let current = nvim.get_current_win().await?;
+    if is_text_win(&current).await? {
+        return Ok(current);
+    }
+    // `winnr('#')` is the previous window of the tab, 0 if there is none
+    if let Some(id) = nvim
+        .eval("win_getid(winnr('#'))")
+        .await?
+        .as_i64()
+        .filter(|id| *id != 0)
+    {
+        let previous = Window::new(Value::from(id), nvim.clone());
+        if is_text_win(&previous).await? {
+            return Ok(previous);
+        }
+    }
     for win in nvim.list_wins().await? {
-        // If the windows current buffer is a normal buffer (editable)
-        if win.get_buf().await?.get_option("buftype").await? == Value::String("".into()) {
+        if is_text_win(&win).await? {
             return Ok(win);
         }
     }
     Err(Error::NoWindow)
 }
+
+/// Whether the window's current buffer is a normal, editable buffer.
+async fn is_text_win<W>(win: &Window<W>) -> Result<bool>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    Ok(win.get_buf().await?.get_option("buftype").await? == Value::String("".into()))
+}
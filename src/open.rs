@@ -0,0 +1,15 @@
+use log::debug;
+use tourust::{
+    Ref,
+    error::{Error, Result},
+};
+
+/// Open the file of a ref in the default application of the OS, e.g. through `xdg-open` or
+/// `open` on macOS.
+///
+/// Only the file is passed on, there is no portable way to ask an arbitrary application for a
+/// line, so the ref's position is lost. Use an editor backend like `nvim` to land on the symbol.
+pub fn open_ref(r: &Ref) -> Result<()> {
+    debug!("opening: {}", r.file.display());
+    open::that(&r.file).map_err(|err| Error::Open(r.file.to_owned(), err))
+}
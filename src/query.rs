@@ -0,0 +1,190 @@
+use std::str::FromStr;
+
+use tourust::{Ref, RefKind};
+
+/// A query with its filters and fzf's operators taken off. Words in front like `@fn ` or `#pub `
+/// keep only items of that kind or public ones. Then a leading `'` asks for an exact substring,
+/// a leading `^` anchors it at the start and a trailing `$` at the end. Any operator makes the
+/// match exact, ignoring ASCII case, otherwise it is up to the fuzzy matcher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Query<'a> {
+    /// The text to look for, without filters and operators
+    pub pattern: &'a str,
+    /// Only items of this kind, from `@kind `
+    pub kind: Option<RefKind>,
+    /// Only `pub` and `pub(crate)` items, from `#pub `
+    pub public: bool,
+    pub exact: bool,
+    pub prefix: bool,
+    pub suffix: bool,
+}
+
+impl<'a> Query<'a> {
+    pub fn parse(input: &'a str) -> Self {
+        let (mut kind, mut public) = (None, false);
+        let mut rest = input;
+        // A filter only applies once the space after it is typed, until then it is searched for
+        while let Some((word, after)) = rest.split_once(' ') {
+            match word.strip_prefix('@').map(RefKind::from_str) {
+                Some(Ok(filter)) => kind = Some(filter),
+                _ if word == "#pub" => public = true,
+                _ => break,
+            }
+            rest = after.trim_start_matches(' ');
+        }
+        let (pattern, exact) = match rest.strip_prefix('\'') {
+            Some(rest) => (rest, true),
+            None => (rest, false),
+        };
+        let (pattern, prefix) = match pattern.strip_prefix('^') {
+            Some(rest) => (rest, true),
+            None => (pattern, false),
+        };
+        let (pattern, suffix) = match pattern.strip_suffix('$') {
+            Some(rest) => (rest, true),
+            None => (pattern, false),
+        };
+        Self {
+            pattern,
+            kind,
+            public,
+            exact,
+            prefix,
+            suffix,
+        }
+    }
+
+    /// Whether the fuzzy matcher decides what matches, i.e. no operator was given.
+    pub fn is_fuzzy(&self) -> bool {
+        !self.exact && !self.prefix && !self.suffix
+    }
+
+    /// Whether `r` passes the query's filters, regardless of the pattern.
+    pub fn admits(&self, r: &Ref) -> bool {
+        self.kind.is_none_or(|kind| r.kind == kind) && (!self.public || r.visibility.is_exported())
+    }
+
+    /// Whether `text` satisfies the operators, always true for a fuzzy query.
+    pub fn matches(&self, text: &str) -> bool {
+        if self.is_fuzzy() {
+            return true;
+        }
+        let text = text.to_ascii_lowercase();
+        let pattern = self.pattern.to_ascii_lowercase();
+        match (self.prefix, self.suffix) {
+            (true, true) => text == pattern,
+            (true, false) => text.starts_with(&pattern),
+            (false, true) => text.ends_with(&pattern),
+            (false, false) => text.contains(&pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tourust::Visibility;
+
+    use super::*;
+
+    const SIGS: [&str; 4] = [
+        "fn parse_file()",
+        "fn reparse()",
+        "struct Parser",
+        "fn parse()",
+    ];
+
+    /// The signatures `input` matches by its operators alone.
+    fn matching(input: &str) -> Vec<&'static str> {
+        let query = Query::parse(input);
+        SIGS.into_iter().filter(|sig| query.matches(sig)).collect()
+    }
+
+    #[test]
+    fn plain_queries_are_fuzzy() {
+        let query = Query::parse("prs");
+        assert!(query.is_fuzzy());
+        assert_eq!(query.pattern, "prs");
+        assert_eq!(matching("prs"), SIGS);
+    }
+
+    #[test]
+    fn quote_asks_for_a_substring() {
+        let query = Query::parse("'parse");
+        assert!(query.exact && !query.is_fuzzy());
+        assert_eq!(query.pattern, "parse");
+        assert_eq!(
+            matching("'parse"),
+            [
+                "fn parse_file()",
+                "fn reparse()",
+                "struct Parser",
+                "fn parse()"
+            ]
+        );
+        assert_eq!(matching("'parse_"), ["fn parse_file()"]);
+    }
+
+    #[test]
+    fn caret_anchors_at_the_start() {
+        assert_eq!(matching("^struct"), ["struct Parser"]);
+        assert_eq!(matching("^fn re"), ["fn reparse()"]);
+    }
+
+    #[test]
+    fn dollar_anchors_at_the_end() {
+        assert_eq!(matching("parser$"), ["struct Parser"]);
+        assert_eq!(matching("parse()$"), ["fn reparse()", "fn parse()"]);
+    }
+
+    #[test]
+    fn both_anchors_ask_for_the_whole_signature() {
+        assert_eq!(matching("^fn parse()$"), ["fn parse()"]);
+        // Ignoring ASCII case
+        assert_eq!(matching("^STRUCT PARSER$"), ["struct Parser"]);
+    }
+
+    #[test]
+    fn operators_combine_with_a_quote() {
+        let query = Query::parse("'^fn$");
+        assert!(query.exact && query.prefix && query.suffix);
+        assert_eq!(query.pattern, "fn");
+    }
+
+    #[test]
+    fn filters_in_front_are_taken_off() {
+        let query = Query::parse("@struct #pub  parse");
+        assert_eq!(query.kind, Some(RefKind::Struct));
+        assert!(query.public);
+        assert_eq!(query.pattern, "parse");
+        assert!(query.is_fuzzy());
+        // Operators still apply after the filters
+        let query = Query::parse("@fn ^parse");
+        assert_eq!(
+            (query.kind, query.pattern, query.prefix),
+            (Some(RefKind::Fn), "parse", true)
+        );
+    }
+
+    #[test]
+    fn unfinished_or_unknown_filters_are_searched_for() {
+        assert_eq!(Query::parse("@struct").pattern, "@struct");
+        assert_eq!(Query::parse("@struct").kind, None);
+        assert_eq!(Query::parse("@thing parse").pattern, "@thing parse");
+        // Only words in front are filters
+        let query = Query::parse("parse @fn x");
+        assert_eq!((query.pattern, query.kind), ("parse @fn x", None));
+    }
+
+    #[test]
+    fn filters_admit_by_kind_and_visibility() {
+        let item = |kind, visibility| Ref {
+            kind,
+            visibility,
+            ..Default::default()
+        };
+        let query = Query::parse("@struct #pub ");
+        assert!(query.admits(&item(RefKind::Struct, Visibility::Public)));
+        assert!(!query.admits(&item(RefKind::Struct, Visibility::Private)));
+        assert!(!query.admits(&item(RefKind::Fn, Visibility::Public)));
+    }
+}
@@ -0,0 +1,1106 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize, Serializer};
+use syn::{
+    Field, ForeignItem, Generics, Ident, ImplItemFn, Item, ItemEnum, ItemForeignMod, ItemImpl,
+    ItemStruct, StaticMutability, Variant, ext::IdentExt, spanned::Spanned,
+};
+
+use crate::{
+    attrs::{HasAttrs, aliases, cfg_predicate, docs},
+    index::IndexOptions,
+    target::CargoTarget,
+};
+
+#[derive(Hash, Default, Eq, PartialEq, Clone, Debug, Serialize)]
+pub struct Ref {
+    pub line: usize,
+    pub column: usize,
+    /// The last line of the whole item, e.g. the closing brace of a function.
+    pub end_line: usize,
+    #[serde(serialize_with = "serialize_path_lossy")]
+    pub file: PathBuf,
+    pub sig: String,
+    /// `sig` without its visibility and with whitespace collapsed, queries are matched against
+    /// this so modifiers don't skew the ranking.
+    #[serde(skip)]
+    pub match_sig: String,
+    /// Other names queries match the item by, e.g. from `#[doc(alias = "malloc")]`.
+    pub aliases: Vec<String>,
+    /// The item's doc comment, without the leading `///`.
+    pub docs: Option<String>,
+    /// The bare identifier of the item, without any signature decoration.
+    pub name: String,
+    pub kind: RefKind,
+    pub visibility: Visibility,
+    /// Modules enclosing the item, outermost first and without the leading `crate`.
+    pub module_path: Vec<String>,
+    /// The type an item inside an `impl` block is implemented on.
+    pub owner: Option<String>,
+    /// The trait an `impl` block implements, for the methods inside it, e.g. `Display`.
+    pub impl_trait: Option<String>,
+    /// The line of the `impl` header an item inside an `impl` block belongs to, so the preview
+    /// can show which type it is on.
+    pub impl_line: Option<usize>,
+    /// The `#[cfg(..)]` predicate the item is compiled under, e.g. `feature = "x"`.
+    pub cfg: Option<String>,
+    /// Whether the item is an `async fn`.
+    pub is_async: bool,
+    /// Whether the item is an `unsafe fn`.
+    pub is_unsafe: bool,
+    /// The Cargo target the item's file is compiled into, if it is in a package.
+    pub target: Option<CargoTarget>,
+}
+
+/// Serialize a path as a string even if it isn't valid UTF-8, replacing the invalid parts.
+fn serialize_path_lossy<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+impl Ref {
+    /// The fully qualified path of the item, e.g. `crate::app::App::find_refs`, or
+    /// `crate::error::<Error as Display>::fmt` for trait methods.
+    pub fn path(&self) -> String {
+        let owner = match (&self.owner, &self.impl_trait) {
+            (Some(owner), Some(impl_trait)) => Some(format!("<{owner} as {impl_trait}>")),
+            (owner, _) => owner.clone(),
+        };
+        let mut segments = vec!["crate"];
+        segments.extend(self.module_path.iter().map(String::as_str));
+        segments.extend(owner.as_deref());
+        // An import's name is the imported path itself, which would read as a submodule, and a
+        // derive's is already in the owner
+        if !matches!(self.kind, RefKind::Use | RefKind::Derive) {
+            segments.push(&self.name);
+        }
+        segments.join("::")
+    }
+
+    /// How many lines the item spans, from the line it starts on to `end_line`.
+    pub fn line_count(&self) -> usize {
+        self.end_line.saturating_sub(self.line) + 1
+    }
+
+    /// The signature as shown in the results, without the `r#` of raw identifiers.
+    pub fn display_sig(&self) -> String {
+        strip_raw(&self.sig)
+    }
+
+    /// Which trait impl a function comes from, e.g. `impl Display::fmt for Error`, or `None`
+    /// for functions of inherent impls and anything that isn't a function.
+    pub fn trait_sig(&self) -> Option<String> {
+        if !matches!(self.kind, RefKind::Fn | RefKind::Method) {
+            return None;
+        }
+        let (owner, impl_trait) = (self.owner.as_ref()?, self.impl_trait.as_ref()?);
+        Some(strip_raw(&format!(
+            "impl {impl_trait}::{} for {owner}",
+            self.name
+        )))
+    }
+
+    /// The source of the whole item, from the line it starts on to `end_line`.
+    pub fn source(&self) -> io::Result<String> {
+        let src = fs::read_to_string(&self.file)?;
+        let lines: Vec<&str> = src
+            .lines()
+            .skip(self.line.saturating_sub(1))
+            .take(self.end_line.saturating_sub(self.line) + 1)
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    /// The byte offset of `column` in its line, which is what editors position the cursor by.
+    /// Columns from syn count chars, which differs once a multi-byte char comes before the
+    /// item. Falls back to `column` if the line can't be read.
+    pub fn byte_column(&self) -> usize {
+        let Ok(src) = fs::read_to_string(&self.file) else {
+            return self.column;
+        };
+        let Some(text) = src.lines().nth(self.line.saturating_sub(1)) else {
+            return self.column;
+        };
+        text.char_indices()
+            .nth(self.column)
+            .map_or(text.len(), |(i, _)| i)
+    }
+
+    /// A ref standing in for the impl a `#[derive(..)]` on this item generates, e.g.
+    /// `derive Clone for Foo`. Derived impls don't exist in the source, so it points here. A
+    /// derive from a `#[cfg_attr(..)]` only exists under its `condition` as well.
+    pub fn derived(&self, derive: &str, condition: Option<&str>) -> Ref {
+        let sig = format!("derive {} for {}", derive, self.name);
+        Ref {
+            match_sig: normalize_sig(&sig),
+            sig,
+            name: derive.to_owned(),
+            kind: RefKind::Derive,
+            owner: Some(self.name.to_owned()),
+            impl_trait: Some(derive.to_owned()),
+            cfg: match (&self.cfg, condition) {
+                (Some(cfg), Some(condition)) => Some(format!("all({cfg}, {condition})")),
+                (cfg, condition) => cfg.clone().or(condition.map(str::to_owned)),
+            },
+            aliases: Vec::new(),
+            docs: None,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(
+    Hash, Default, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RefKind {
+    #[default]
+    Fn,
+    /// A function of an `impl` taking `self`, an associated function without a receiver is a
+    /// [`RefKind::Fn`]
+    Method,
+    Mod,
+    Enum,
+    Trait,
+    Struct,
+    Use,
+    Type,
+    Impl,
+    Const,
+    Macro,
+    Static,
+    Union,
+    /// A named field of a struct
+    Field,
+    /// A variant of an enum
+    Variant,
+    /// A trait implemented through `#[derive(..)]`, located at the deriving item
+    Derive,
+}
+
+impl RefKind {
+    pub const ALL: [RefKind; 16] = [
+        RefKind::Fn,
+        RefKind::Method,
+        RefKind::Mod,
+        RefKind::Enum,
+        RefKind::Trait,
+        RefKind::Struct,
+        RefKind::Use,
+        RefKind::Type,
+        RefKind::Impl,
+        RefKind::Const,
+        RefKind::Macro,
+        RefKind::Static,
+        RefKind::Union,
+        RefKind::Field,
+        RefKind::Variant,
+        RefKind::Derive,
+    ];
+}
+
+impl FromStr for RefKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RefKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| format!("unknown item kind `{s}`"))
+    }
+}
+
+impl fmt::Display for RefKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RefKind::Fn => "fn",
+            RefKind::Method => "method",
+            RefKind::Mod => "mod",
+            RefKind::Enum => "enum",
+            RefKind::Trait => "trait",
+            RefKind::Struct => "struct",
+            RefKind::Use => "use",
+            RefKind::Type => "type",
+            RefKind::Impl => "impl",
+            RefKind::Const => "const",
+            RefKind::Macro => "macro",
+            RefKind::Static => "static",
+            RefKind::Union => "union",
+            RefKind::Field => "field",
+            RefKind::Variant => "variant",
+            RefKind::Derive => "derive",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Hash, Default, Eq, PartialEq, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// `pub`
+    Public,
+    /// `pub(crate)`
+    Crate,
+    /// `pub(super)` or `pub(in path)`
+    Restricted,
+    #[default]
+    Private,
+}
+
+impl Visibility {
+    /// Whether the item is reachable from outside its own module tree, i.e. `pub` or
+    /// `pub(crate)`.
+    pub fn is_exported(&self) -> bool {
+        matches!(self, Visibility::Public | Visibility::Crate)
+    }
+}
+
+impl From<&syn::Visibility> for Visibility {
+    fn from(value: &syn::Visibility) -> Self {
+        match value {
+            syn::Visibility::Public(_) => Visibility::Public,
+            syn::Visibility::Restricted(res) if res.path.is_ident("crate") => Visibility::Crate,
+            syn::Visibility::Restricted(res) if res.path.is_ident("self") => Visibility::Private,
+            syn::Visibility::Restricted(_) => Visibility::Restricted,
+            syn::Visibility::Inherited => Visibility::Private,
+        }
+    }
+}
+
+/// The ref of an item in `file`. Items that never get one, e.g. `extern crate` or an `extern`
+/// block, are handed back.
+impl TryFrom<(Item, PathBuf)> for Ref {
+    type Error = Item;
+
+    fn try_from(value: (Item, PathBuf)) -> Result<Self, Self::Error> {
+        let (item, file) = value;
+        let (Some(kind), Some(anchor), Some(name), Some(visibility)) =
+            (kind(&item), anchor(&item), name(&item), visibility(&item))
+        else {
+            return Err(item);
+        };
+        let sig = item.display();
+        Ok(Self {
+            line: anchor.start().line,
+            column: anchor.start().column,
+            end_line: item.span().end().line,
+            file,
+            match_sig: normalize_sig(&sig),
+            sig,
+            name,
+            kind,
+            visibility,
+            module_path: Vec::new(),
+            owner: None,
+            impl_trait: None,
+            impl_line: None,
+            cfg: cfg_predicate(item.attrs()),
+            aliases: aliases(item.attrs()),
+            docs: docs(item.attrs()),
+            is_async: matches!(&item, Item::Fn(item) if item.sig.asyncness.is_some()),
+            is_unsafe: matches!(&item, Item::Fn(item) if item.sig.unsafety.is_some()),
+            target: None,
+        })
+    }
+}
+
+impl From<(ImplItemFn, &ItemImpl, PathBuf)> for Ref {
+    fn from(value: (ImplItemFn, &ItemImpl, PathBuf)) -> Self {
+        let (item, imp, file) = value;
+        // Trait methods take the visibility of the trait rather than declaring their own
+        let visibility = if imp.trait_.is_some() {
+            Visibility::Public
+        } else {
+            (&item.vis).into()
+        };
+        let sig = item.display();
+        Self {
+            line: item.sig.span().start().line,
+            column: item.sig.span().start().column,
+            end_line: item.span().end().line,
+            file,
+            match_sig: normalize_sig(&sig),
+            sig,
+            name: item.sig.ident.unraw().to_string(),
+            kind: if item.sig.receiver().is_some() {
+                RefKind::Method
+            } else {
+                RefKind::Fn
+            },
+            visibility,
+            module_path: Vec::new(),
+            owner: imp
+                .self_ty
+                .span()
+                .source_text()
+                .map(|ty| collapse_whitespace(&ty)),
+            impl_trait: imp
+                .trait_
+                .as_ref()
+                .and_then(|(_, path, _)| path.segments.last().span().source_text()),
+            impl_line: Some(imp.impl_token.span.start().line),
+            cfg: cfg_predicate(&item.attrs),
+            aliases: aliases(&item.attrs),
+            docs: docs(&item.attrs),
+            is_async: item.sig.asyncness.is_some(),
+            is_unsafe: item.sig.unsafety.is_some(),
+            target: None,
+        }
+    }
+}
+
+impl From<(Field, &ItemStruct, PathBuf)> for Ref {
+    fn from(value: (Field, &ItemStruct, PathBuf)) -> Self {
+        let (field, strukt, file) = value;
+        let name = field
+            .ident
+            .as_ref()
+            .map_or(String::new(), |ident| ident.unraw().to_string());
+        let anchor = field.ident.as_ref().map_or(field.span(), Ident::span);
+        let sig = format!(
+            "{}.{}: {}",
+            strukt.ident,
+            name,
+            field.ty.span().source_text().unwrap_or("UNKNOWN".into())
+        );
+        Self {
+            line: anchor.start().line,
+            column: anchor.start().column,
+            end_line: field.span().end().line,
+            file,
+            match_sig: normalize_sig(&sig),
+            sig,
+            name,
+            kind: RefKind::Field,
+            visibility: (&field.vis).into(),
+            module_path: Vec::new(),
+            owner: Some(strukt.ident.unraw().to_string()),
+            impl_trait: None,
+            impl_line: None,
+            cfg: cfg_predicate(&field.attrs),
+            aliases: aliases(&field.attrs),
+            docs: docs(&field.attrs),
+            is_async: false,
+            is_unsafe: false,
+            target: None,
+        }
+    }
+}
+
+impl From<(Variant, &ItemEnum, PathBuf)> for Ref {
+    fn from(value: (Variant, &ItemEnum, PathBuf)) -> Self {
+        let (variant, enm, file) = value;
+        let sig = format!("{}::{}", enm.ident, variant.ident);
+        Self {
+            line: variant.ident.span().start().line,
+            column: variant.ident.span().start().column,
+            end_line: variant.span().end().line,
+            file,
+            match_sig: normalize_sig(&sig),
+            sig,
+            name: variant.ident.unraw().to_string(),
+            kind: RefKind::Variant,
+            // Variants are always as visible as their enum
+            visibility: (&enm.vis).into(),
+            module_path: Vec::new(),
+            owner: Some(enm.ident.unraw().to_string()),
+            impl_trait: None,
+            impl_line: None,
+            cfg: cfg_predicate(&variant.attrs),
+            aliases: aliases(&variant.attrs),
+            docs: docs(&variant.attrs),
+            is_async: false,
+            is_unsafe: false,
+            target: None,
+        }
+    }
+}
+
+/// The ref for a function, static or type declared in an `extern "C" { .. }` block, with the
+/// block's ABI in its signature, e.g. `pub extern "C" fn abs(x: i32) -> i32`. `None` for
+/// macros and anything else syn can't make sense of.
+pub(crate) fn foreign_ref(
+    item: &ForeignItem,
+    foreign: &ItemForeignMod,
+    file: PathBuf,
+) -> Option<Ref> {
+    let abi = foreign.abi.span().source_text().unwrap_or("extern".into());
+    let vis = |vis: &syn::Visibility| vis.span().source_text().map_or(String::new(), |e| e + " ");
+    let (ident, vis_of, kind, sig, anchor) = match item {
+        ForeignItem::Fn(item) => (
+            &item.sig.ident,
+            &item.vis,
+            RefKind::Fn,
+            format!(
+                "{}{abi} {}",
+                vis(&item.vis),
+                item.sig
+                    .span()
+                    .source_text()
+                    .unwrap_or("MISSING SOURCE TEXT".into())
+            ),
+            item.sig.span(),
+        ),
+        ForeignItem::Static(item) => (
+            &item.ident,
+            &item.vis,
+            RefKind::Static,
+            format!(
+                "{}{abi} static {}{}: {}",
+                vis(&item.vis),
+                match item.mutability {
+                    StaticMutability::Mut(_) => "mut ",
+                    _ => "",
+                },
+                item.ident,
+                collapse_whitespace(&item.ty.span().source_text().unwrap_or("UNKNOWN".into()))
+            ),
+            item.ident.span(),
+        ),
+        ForeignItem::Type(item) => (
+            &item.ident,
+            &item.vis,
+            RefKind::Type,
+            format!("{}{abi} type {}", vis(&item.vis), item.ident),
+            item.ident.span(),
+        ),
+        _ => return None,
+    };
+    let attrs = item.attrs();
+    Some(Ref {
+        line: anchor.start().line,
+        column: anchor.start().column,
+        end_line: item.span().end().line,
+        file,
+        match_sig: normalize_sig(&sig),
+        sig,
+        name: ident.unraw().to_string(),
+        kind,
+        visibility: vis_of.into(),
+        module_path: Vec::new(),
+        owner: None,
+        impl_trait: None,
+        impl_line: None,
+        cfg: cfg_predicate(attrs),
+        aliases: aliases(attrs),
+        docs: docs(attrs),
+        is_async: false,
+        is_unsafe: false,
+        target: None,
+    })
+}
+
+/// The span a ref points at, usually the item's identifier, `None` for items that are never
+/// indexed.
+fn anchor(item: &Item) -> Option<Span> {
+    Some(match item {
+        Item::Fn(item) => item.sig.span(),
+        Item::Mod(item) => item.ident.span(),
+        Item::Enum(item) => item.ident.span(),
+        Item::Trait(item) => item.ident.span(),
+        Item::Struct(item) => item.ident.span(),
+        Item::Impl(item) => item.self_ty.span(),
+        Item::Macro(item) => item.ident.span(),
+        Item::Union(item) => item.ident.span(),
+        Item::Use(_) | Item::Type(_) | Item::Const(_) | Item::Static(_) => item.span(),
+        _ => return None,
+    })
+}
+
+/// The kind of ref an item becomes, `None` for items that are never indexed.
+fn kind(item: &Item) -> Option<RefKind> {
+    match item {
+        Item::Fn(_) => Some(RefKind::Fn),
+        Item::Mod(_) => Some(RefKind::Mod),
+        Item::Enum(_) => Some(RefKind::Enum),
+        Item::Trait(_) => Some(RefKind::Trait),
+        Item::Struct(_) => Some(RefKind::Struct),
+        Item::Use(_) => Some(RefKind::Use),
+        Item::Type(_) => Some(RefKind::Type),
+        Item::Impl(_) => Some(RefKind::Impl),
+        Item::Const(_) => Some(RefKind::Const),
+        Item::Macro(_) => Some(RefKind::Macro),
+        Item::Static(_) => Some(RefKind::Static),
+        Item::Union(_) => Some(RefKind::Union),
+        _ => None,
+    }
+}
+
+/// How visible an item is, `None` for items that are never indexed.
+fn visibility(item: &Item) -> Option<Visibility> {
+    Some(match item {
+        Item::Fn(item) => (&item.vis).into(),
+        Item::Mod(item) => (&item.vis).into(),
+        Item::Enum(item) => (&item.vis).into(),
+        Item::Trait(item) => (&item.vis).into(),
+        Item::Struct(item) => (&item.vis).into(),
+        Item::Use(item) => (&item.vis).into(),
+        Item::Type(item) => (&item.vis).into(),
+        Item::Const(item) => (&item.vis).into(),
+        Item::Static(item) => (&item.vis).into(),
+        Item::Union(item) => (&item.vis).into(),
+        // Impls have no visibility of their own, they are as visible as the type they are for
+        Item::Impl(_) => Visibility::Public,
+        Item::Macro(item) => {
+            if item
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("macro_export"))
+            {
+                Visibility::Public
+            } else {
+                Visibility::Private
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// The bare identifier of an item, e.g. `find_refs` for `pub fn find_refs(..)`, `None` for
+/// items that are never indexed.
+fn name(item: &Item) -> Option<String> {
+    Some(match item {
+        Item::Fn(item) => item.sig.ident.unraw().to_string(),
+        Item::Mod(item) => item.ident.unraw().to_string(),
+        Item::Enum(item) => item.ident.unraw().to_string(),
+        Item::Trait(item) => item.ident.unraw().to_string(),
+        Item::Struct(item) => item.ident.unraw().to_string(),
+        Item::Type(item) => item.ident.unraw().to_string(),
+        Item::Const(item) => item.ident.unraw().to_string(),
+        Item::Static(item) => item.ident.unraw().to_string(),
+        Item::Union(item) => item.ident.unraw().to_string(),
+        Item::Macro(item) => match &item.ident {
+            Some(ident) => ident.unraw().to_string(),
+            None => item
+                .mac
+                .path
+                .segments
+                .last()
+                .map_or(String::new(), |seg| seg.ident.unraw().to_string()),
+        },
+        // A type may span lines, e.g. its generics, the name doesn't
+        Item::Impl(item) => collapse_whitespace(
+            &item
+                .self_ty
+                .span()
+                .source_text()
+                .unwrap_or("UNKNOWN".into()),
+        ),
+        Item::Use(item) => item.tree.span().source_text().unwrap_or("UNKNOWN".into()),
+        _ => return None,
+    })
+}
+
+pub trait IsRelevant {
+    /// Whether the item itself gets a ref under `opts`. Its children are judged separately.
+    fn is_relevant(&self, opts: &IndexOptions) -> bool;
+}
+
+impl IsRelevant for Item {
+    fn is_relevant(&self, opts: &IndexOptions) -> bool {
+        kind(self).is_some_and(|kind| opts.kinds.contains(&kind))
+    }
+}
+
+/// Join a possibly multi-line snippet into a single line with single spaces.
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `text` with the `r#` of raw identifiers taken off, e.g. `fn r#match(r#type: u8)` becomes
+/// `fn match(type: u8)`. Raw strings like `r#"..."#` are left alone.
+fn strip_raw(text: &str) -> String {
+    let is_ident_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find("r#") {
+        let starts_ident = !rest[..i].ends_with(is_ident_char);
+        let before_ident = rest[i + 2..].starts_with(|ch: char| ch.is_alphabetic() || ch == '_');
+        stripped.push_str(&rest[..i]);
+        if !(starts_ident && before_ident) {
+            stripped.push_str("r#");
+        }
+        rest = &rest[i + 2..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// A signature without its leading visibility, e.g. `pub(crate) fn  parse()` becomes
+/// `fn parse()`, and without the `r#` of raw identifiers so `match` finds `r#match`.
+fn normalize_sig(sig: &str) -> String {
+    let sig = sig.trim_start();
+    let rest = match sig.strip_prefix("pub") {
+        // `pub(crate)`, `pub(super)`, `pub(in path)`
+        Some(rest) if rest.starts_with('(') => rest.split_once(')').map_or(sig, |(_, rest)| rest),
+        Some(rest) if rest.starts_with(char::is_whitespace) => rest,
+        _ => sig,
+    };
+    strip_raw(&collapse_whitespace(rest))
+}
+
+/// How much of an aliased type is shown before it is cut off.
+const MAX_ALIASED_LEN: usize = 60;
+
+/// Cut `text` down to at most `max` characters, marking that it was cut with an ellipsis.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+    let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// The `<...>` parameter list of an item, empty if it has none.
+fn generics_text(generics: &Generics) -> String {
+    if generics.params.is_empty() {
+        return String::new();
+    }
+    collapse_whitespace(&generics.span().source_text().unwrap_or_default())
+}
+
+pub trait ItemDisplay {
+    fn display(&self) -> String;
+}
+
+impl ItemDisplay for Item {
+    fn display(&self) -> String {
+        match self {
+            Item::Fn(item) => {
+                format!(
+                    "{}{}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.sig
+                        .span()
+                        .source_text()
+                        .unwrap_or("MISSING SOURCE TEXT".to_string())
+                )
+            }
+            Item::Mod(item) => {
+                format!(
+                    "{}mod {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident
+                )
+            }
+            Item::Enum(item) => {
+                format!(
+                    "{}enum {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident
+                )
+            }
+            Item::Trait(item) => {
+                format!(
+                    "{}trait {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident
+                )
+            }
+            Item::Struct(item) => {
+                format!(
+                    "{}struct {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident
+                )
+            }
+            Item::Use(item) => item.span().source_text().unwrap_or(String::from("UNKNOWN")),
+            Item::Type(item) => {
+                format!(
+                    "{}type {}{} = {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident,
+                    generics_text(&item.generics),
+                    truncate(
+                        &collapse_whitespace(
+                            &item.ty.span().source_text().unwrap_or("UNKNOWN".into())
+                        ),
+                        MAX_ALIASED_LEN
+                    )
+                )
+            }
+            Item::Impl(item) => {
+                if let Some((_, pth, _)) = &item.trait_ {
+                    format!(
+                        "impl {} for {}",
+                        pth.segments
+                            .last()
+                            .span()
+                            .source_text()
+                            .unwrap_or("UNKNOWN".into()),
+                        item.self_ty
+                            .span()
+                            .source_text()
+                            .unwrap_or("UNKNOWN".into())
+                    )
+                } else {
+                    format!(
+                        "impl {}",
+                        item.self_ty
+                            .span()
+                            .source_text()
+                            .unwrap_or("UNKNOWN".into())
+                    )
+                }
+            }
+            // Only the declaration, the initializer may be arbitrarily long
+            Item::Const(item) => {
+                format!(
+                    "{}const {}: {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident,
+                    collapse_whitespace(&item.ty.span().source_text().unwrap_or("UNKNOWN".into()))
+                )
+            }
+            Item::Macro(item) => item.ident.span().source_text().unwrap_or("UNKNOWN".into()),
+            Item::Static(item) => {
+                format!(
+                    "{}static {}{}: {}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    match item.mutability {
+                        StaticMutability::Mut(_) => "mut ",
+                        _ => "",
+                    },
+                    item.ident,
+                    collapse_whitespace(&item.ty.span().source_text().unwrap_or("UNKNOWN".into()))
+                )
+            }
+            Item::Union(item) => {
+                format!(
+                    "{}union {}{}",
+                    item.vis
+                        .span()
+                        .source_text()
+                        .map_or(String::new(), |e| e + " "),
+                    item.ident,
+                    generics_text(&item.generics)
+                )
+            }
+            _ => "IRRELEVANT".into(),
+        }
+    }
+}
+
+impl ItemDisplay for ImplItemFn {
+    fn display(&self) -> String {
+        format!(
+            "{}{}",
+            self.vis
+                .span()
+                .source_text()
+                .map_or(String::new(), |e| e + " "),
+            self.sig
+                .span()
+                .source_text()
+                .unwrap_or("MISSING SOURCE TEXT".to_string())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::ImplItem;
+
+    use super::*;
+
+    /// The refs of the functions in an `impl` block.
+    fn impl_fns(src: &str) -> Vec<Ref> {
+        let imp: ItemImpl = syn::parse_str(src).unwrap();
+        imp.items
+            .iter()
+            .filter_map(|item| match item {
+                ImplItem::Fn(f) => Some(Ref::from((f.clone(), &imp, PathBuf::from("lib.rs")))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inherent_methods_have_no_trait() {
+        let refs = impl_fns("impl Error { pub fn new() -> Self { Error } fn kind(&self) {} }");
+        assert_eq!(refs.len(), 2);
+        for r in &refs {
+            assert_eq!(r.impl_trait, None);
+            assert_eq!(r.trait_sig(), None);
+        }
+        assert_eq!(refs[1].path(), "crate::Error::kind");
+    }
+
+    #[test]
+    fn trait_methods_name_their_impl() {
+        let refs = impl_fns(
+            "impl fmt::Display for Error { fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { Ok(()) } }",
+        );
+        let [r] = refs.as_slice() else {
+            panic!("expected one ref, got {refs:?}");
+        };
+        assert_eq!(r.kind, RefKind::Method);
+        assert_eq!(r.impl_trait.as_deref(), Some("Display"));
+        assert_eq!(
+            r.trait_sig().as_deref(),
+            Some("impl Display::fmt for Error")
+        );
+        assert_eq!(r.path(), "crate::<Error as Display>::fmt");
+    }
+
+    #[test]
+    fn trait_associated_functions_name_their_impl() {
+        let refs = impl_fns("impl Default for Config { fn default() -> Self { todo!() } }");
+        assert_eq!(refs[0].kind, RefKind::Fn);
+        assert_eq!(
+            refs[0].trait_sig().as_deref(),
+            Some("impl Default::default for Config")
+        );
+    }
+
+    #[test]
+    fn methods_are_told_from_associated_functions() {
+        let refs = impl_fns(
+            "impl Shape {
+                pub fn new() -> Self { Shape }
+                fn with_side(side: u32) -> Self { Shape }
+                fn area(&self) -> u32 { 0 }
+                fn grow(&mut self) {}
+                fn into_box(self: Box<Self>) {}
+                fn consume(mut self) {}
+            }",
+        );
+        let kinds: Vec<_> = refs.iter().map(|r| (r.name.as_str(), r.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("new", RefKind::Fn),
+                ("with_side", RefKind::Fn),
+                ("area", RefKind::Method),
+                ("grow", RefKind::Method),
+                ("into_box", RefKind::Method),
+                ("consume", RefKind::Method),
+            ]
+        );
+        assert_eq!(RefKind::Fn.to_string(), "fn");
+        assert_eq!(RefKind::Method.to_string(), "method");
+    }
+
+    #[test]
+    fn methods_carry_their_impl_line() {
+        let refs = impl_fns(
+            "#[cfg(unix)]
+            impl Shape {
+                fn area(&self) -> u32 { 0 }
+
+                fn grow(&mut self) {}
+            }",
+        );
+        let lines: Vec<_> = refs.iter().map(|r| (r.line, r.impl_line)).collect();
+        // The `impl` keyword's line, past the attributes
+        assert_eq!(lines, [(3, Some(2)), (5, Some(2))]);
+        assert_eq!(item_ref("fn free() {}").impl_line, None);
+    }
+
+    #[test]
+    fn multi_line_impl_types_are_named_on_one_line() {
+        let src = "impl<T> Foo<\n    T,\n> {\n    fn get(&self) {}\n}";
+        assert_eq!(item_ref(src).name, "Foo< T, >");
+        assert_eq!(impl_fns(src)[0].owner.as_deref(), Some("Foo< T, >"));
+    }
+
+    /// The ref of the single item in `src`.
+    fn item_ref(src: &str) -> Ref {
+        let item: Item = syn::parse_str(src).unwrap();
+        let Ok(r) = Ref::try_from((item, PathBuf::from("lib.rs"))) else {
+            panic!("no ref for {src}");
+        };
+        r
+    }
+
+    #[test]
+    fn visibility_is_classified() {
+        for (src, visibility) in [
+            ("pub fn f() {}", Visibility::Public),
+            ("pub(crate) fn f() {}", Visibility::Crate),
+            ("pub(super) fn f() {}", Visibility::Restricted),
+            ("pub(in crate::a) fn f() {}", Visibility::Restricted),
+            ("pub(self) fn f() {}", Visibility::Private),
+            ("fn f() {}", Visibility::Private),
+        ] {
+            assert_eq!(item_ref(src).visibility, visibility, "{src}");
+        }
+    }
+
+    #[test]
+    fn only_pub_and_pub_crate_are_exported() {
+        assert!(Visibility::Public.is_exported());
+        assert!(Visibility::Crate.is_exported());
+        assert!(!Visibility::Restricted.is_exported());
+        assert!(!Visibility::Private.is_exported());
+    }
+
+    #[test]
+    fn trait_methods_are_public() {
+        let refs = impl_fns("impl Clone for S { fn clone(&self) -> Self { S } }");
+        assert_eq!(refs[0].visibility, Visibility::Public);
+        let refs = impl_fns("impl S { fn private(&self) {} }");
+        assert_eq!(refs[0].visibility, Visibility::Private);
+    }
+
+    #[test]
+    fn consts_show_only_their_declaration() {
+        let r = item_ref(
+            "pub const TABLE: &[(u8, u8)] = &[
+                (1, 2),
+                (3, 4),
+            ];",
+        );
+        assert_eq!(r.sig, "pub const TABLE: &[(u8, u8)]");
+        assert_eq!((r.line, r.end_line), (1, 4));
+    }
+
+    #[test]
+    fn statics_show_only_their_declaration() {
+        assert_eq!(
+            item_ref("static mut COUNT: usize = 0;").sig,
+            "static mut COUNT: usize"
+        );
+    }
+
+    #[test]
+    fn multi_line_types_end_up_on_one_line() {
+        let r = item_ref(
+            "const PAIRS: &[(
+                u8,
+                u8,
+            )] = &[];",
+        );
+        // Line breaks collapse into single spaces
+        assert_eq!(r.sig, "const PAIRS: &[( u8, u8, )]");
+    }
+
+    #[test]
+    fn async_and_unsafe_are_flagged() {
+        let r = item_ref("pub async unsafe fn both() {}");
+        assert!(r.is_async && r.is_unsafe);
+        assert_eq!(r.sig, "pub async unsafe fn both()");
+        let r = item_ref("fn neither() {}");
+        assert!(!r.is_async && !r.is_unsafe);
+        let refs = impl_fns("impl S { async fn run(&self) {} unsafe fn raw() {} }");
+        assert!(refs[0].is_async && !refs[0].is_unsafe);
+        assert!(!refs[1].is_async && refs[1].is_unsafe);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_serialize_lossily() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let r = Ref {
+            file: PathBuf::from(OsStr::from_bytes(b"/src/caf\xe9.rs")),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&r).unwrap();
+        assert_eq!(json["file"], "/src/caf\u{fffd}.rs");
+    }
+
+    #[test]
+    fn type_aliases_render_with_generics() {
+        let r = item_ref(
+            "pub type Map<K, V> = std::collections::HashMap<
+                K,
+                V,
+            >;",
+        );
+        assert_eq!(r.kind, RefKind::Type);
+        assert_eq!(
+            r.sig,
+            "pub type Map<K, V> = std::collections::HashMap< K, V, >"
+        );
+        assert_eq!(item_ref("type Plain = u8;").sig, "type Plain = u8");
+    }
+
+    #[test]
+    fn unions_render_with_generics() {
+        let r = item_ref("pub union U<T: Copy> { a: T, b: u32 }");
+        assert_eq!(r.kind, RefKind::Union);
+        assert_eq!(r.sig, "pub union U<T: Copy>");
+    }
+
+    #[test]
+    fn long_aliased_types_are_cut_off() {
+        let long = format!("({})", vec!["u8"; 40].join(", "));
+        let sig = item_ref(&format!("type Wide = {long};")).sig;
+        let aliased = sig.strip_prefix("type Wide = ").unwrap();
+        assert_eq!(aliased.chars().count(), MAX_ALIASED_LEN);
+        assert!(aliased.ends_with('…'));
+    }
+
+    #[test]
+    fn signatures_are_normalized_for_matching() {
+        for (sig, normalized) in [
+            ("pub fn parse()", "fn parse()"),
+            ("pub(crate)  fn   parse(x: u8)", "fn parse(x: u8)"),
+            ("pub(in crate::a) struct S", "struct S"),
+            ("publish()", "publish()"),
+            ("fn   r#match()", "fn match()"),
+        ] {
+            assert_eq!(normalize_sig(sig), normalized, "{sig}");
+        }
+    }
+
+    #[test]
+    fn end_line_is_the_end_of_the_whole_item() {
+        let r = item_ref(
+            "/// Docs
+            #[inline]
+            fn multi(
+                a: u8,
+            ) -> u8 {
+                a
+            }",
+        );
+        // The ref points at the signature, the item spans from its attributes to the brace
+        assert_eq!((r.line, r.end_line), (3, 7));
+        assert_eq!(r.line_count(), 5);
+        assert_eq!(item_ref("fn one() {}").end_line, 1);
+    }
+
+    #[test]
+    fn methods_end_at_their_own_brace() {
+        let refs = impl_fns(
+            "impl S {
+                fn first(&self) {
+                }
+                fn second(&self) {}
+            }",
+        );
+        assert_eq!((refs[0].line, refs[0].end_line), (2, 3));
+        assert_eq!((refs[1].line, refs[1].end_line), (4, 4));
+    }
+}
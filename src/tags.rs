@@ -0,0 +1,153 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use tourust::{IndexOptions, Ref, RefKind, error::Result, find_refs};
+
+use crate::cli::TagsArgs;
+
+/// The conventional ctags kind letter for a ref, as used by universal-ctags' Rust parser.
+/// Imports and derives are not definitions in the source, so they have no letter.
+fn kind_letter(r: &Ref) -> Option<char> {
+    match r.kind {
+        RefKind::Method => Some('P'),
+        RefKind::Fn if r.owner.is_some() => Some('P'),
+        RefKind::Fn => Some('f'),
+        RefKind::Mod => Some('n'),
+        RefKind::Enum => Some('g'),
+        RefKind::Trait => Some('i'),
+        RefKind::Struct => Some('s'),
+        RefKind::Type => Some('t'),
+        RefKind::Impl => Some('c'),
+        RefKind::Const => Some('C'),
+        RefKind::Macro => Some('M'),
+        RefKind::Static => Some('v'),
+        RefKind::Union => Some('u'),
+        RefKind::Field => Some('m'),
+        RefKind::Variant => Some('e'),
+        RefKind::Use | RefKind::Derive => None,
+    }
+}
+
+pub fn tags(args: TagsArgs, root: &Path, opts: IndexOptions) -> Result<()> {
+    let refs = find_refs(root, opts)?;
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    write_tags(BufWriter::new(out), refs, root)?;
+
+    Ok(())
+}
+
+/// Write the tags of `refs` to `out`, with files relative to `root`.
+fn write_tags(mut out: impl Write, refs: Vec<Ref>, root: &Path) -> io::Result<()> {
+    let mut refs: Vec<Ref> = refs
+        .into_iter()
+        .filter(|r| kind_letter(r).is_some())
+        .collect();
+    // Consumers binary search the file, so entries must be byte-wise sorted by name
+    refs.sort_by(|a, b| {
+        (a.name.as_bytes(), &a.file, a.line).cmp(&(b.name.as_bytes(), &b.file, b.line))
+    });
+
+    writeln!(out, "!_TAG_FILE_FORMAT\t2\t/extended format/")?;
+    writeln!(
+        out,
+        "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/"
+    )?;
+    writeln!(out, "!_TAG_PROGRAM_NAME\ttourust\t//")?;
+    for r in &refs {
+        let file = r.file.strip_prefix(root).unwrap_or(&r.file);
+        writeln!(
+            out,
+            "{}\t{}\t{};\"\t{}",
+            r.name,
+            file.display(),
+            r.line,
+            kind_letter(r).unwrap_or('?')
+        )?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn r(name: &str, kind: RefKind, file: &str, line: usize) -> Ref {
+        Ref {
+            name: name.into(),
+            kind,
+            file: PathBuf::from(file),
+            line,
+            ..Default::default()
+        }
+    }
+
+    /// The tag lines written for `refs`, without the header.
+    fn tag_lines(refs: Vec<Ref>) -> Vec<String> {
+        let mut out = Vec::new();
+        write_tags(&mut out, refs, Path::new("/root")).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("!_TAG_"))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn sorted_bytewise_by_name_then_file_and_line() {
+        let lines = tag_lines(vec![
+            r("parse", RefKind::Fn, "/root/src/b.rs", 3),
+            r("Parser", RefKind::Struct, "/root/src/a.rs", 9),
+            r("parse", RefKind::Fn, "/root/src/a.rs", 20),
+            r("parse", RefKind::Fn, "/root/src/a.rs", 4),
+            r("_private", RefKind::Fn, "/root/src/a.rs", 1),
+        ]);
+        assert_eq!(
+            lines,
+            [
+                "Parser\tsrc/a.rs\t9;\"\ts",
+                "_private\tsrc/a.rs\t1;\"\tf",
+                "parse\tsrc/a.rs\t4;\"\tf",
+                "parse\tsrc/a.rs\t20;\"\tf",
+                "parse\tsrc/b.rs\t3;\"\tf",
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_and_derives_are_left_out() {
+        let lines = tag_lines(vec![
+            r("HashMap", RefKind::Use, "/root/src/a.rs", 1),
+            r("Clone", RefKind::Derive, "/root/src/a.rs", 2),
+            r("new", RefKind::Fn, "/root/src/a.rs", 3),
+        ]);
+        assert_eq!(lines, ["new\tsrc/a.rs\t3;\"\tf"]);
+    }
+
+    #[test]
+    fn header_marks_the_file_sorted() {
+        let mut out = Vec::new();
+        write_tags(&mut out, Vec::new(), Path::new("/root")).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("!_TAG_FILE_FORMAT\t2\t"));
+        assert!(out.contains("!_TAG_FILE_SORTED\t1\t"));
+    }
+
+    #[test]
+    fn multi_line_impl_types_stay_on_one_line() {
+        let item: syn::Item =
+            syn::parse_str("impl<T> Foo<\n    T,\n> {\n    fn get(&self) {}\n}").unwrap();
+        let Ok(imp) = Ref::try_from((item, PathBuf::from("/root/src/a.rs"))) else {
+            panic!("no ref for the impl");
+        };
+        assert_eq!(tag_lines(vec![imp]), ["Foo< T, >\tsrc/a.rs\t1;\"\tc"]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// The kind of Cargo target a file is compiled into.
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CargoTarget {
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+    /// The build script
+    Build,
+}
+
+impl CargoTarget {
+    pub const ALL: [CargoTarget; 6] = [
+        CargoTarget::Lib,
+        CargoTarget::Bin,
+        CargoTarget::Example,
+        CargoTarget::Test,
+        CargoTarget::Bench,
+        CargoTarget::Build,
+    ];
+
+    /// The target `file` belongs to, from the `Cargo.toml` of the package it is in, or `None`
+    /// outside of a package. Targets the manifest declares with a `path` take precedence,
+    /// otherwise Cargo's default layout decides, e.g. `examples/foo.rs` is an example and
+    /// `src/bin/bar.rs` a binary. Modules under `src` count as the library, as telling which
+    /// crate root includes them would take resolving `mod` items.
+    pub fn of(file: &Path) -> Option<Self> {
+        Manifests::default().target_of(file)
+    }
+}
+
+/// The targets declared by each package's manifest, read the first time one of its files is
+/// asked about, so indexing a package parses its `Cargo.toml` once instead of for every file.
+#[derive(Debug, Default)]
+pub(crate) struct Manifests(HashMap<PathBuf, Vec<(PathBuf, CargoTarget)>>);
+
+impl Manifests {
+    /// The same as [`CargoTarget::of`].
+    pub(crate) fn target_of(&mut self, file: &Path) -> Option<CargoTarget> {
+        let package = file
+            .ancestors()
+            .skip(1)
+            .find(|dir| self.0.contains_key(*dir) || dir.join("Cargo.toml").is_file())?;
+        let relative = file.strip_prefix(package).ok()?;
+        let declared = self
+            .0
+            .entry(package.to_owned())
+            .or_insert_with(|| declared_targets(&package.join("Cargo.toml")));
+        if let Some(target) = declared
+            .iter()
+            .find_map(|(path, target)| (path == relative).then_some(*target))
+        {
+            return Some(target);
+        }
+
+        let mut components = relative.components().map(|c| c.as_os_str());
+        Some(match (components.next()?.to_str()?, components.next()) {
+            ("build.rs", None) => CargoTarget::Build,
+            ("examples", Some(_)) => CargoTarget::Example,
+            ("tests", Some(_)) => CargoTarget::Test,
+            ("benches", Some(_)) => CargoTarget::Bench,
+            ("src", Some(name)) if name == "main.rs" || name == "bin" => CargoTarget::Bin,
+            _ => CargoTarget::Lib,
+        })
+    }
+}
+
+/// The files of the targets the manifest names explicitly, e.g. `[[bin]] path = "cli.rs"`,
+/// relative to the package.
+fn declared_targets(manifest: &Path) -> Vec<(PathBuf, CargoTarget)> {
+    let manifest = match fs::read_to_string(manifest)
+        .map_err(|err| err.to_string())
+        .and_then(|src| toml::from_str::<toml::Table>(&src).map_err(|err| err.to_string()))
+    {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            warn!("Ignoring {}: {}", manifest.display(), err);
+            return Vec::new();
+        }
+    };
+    let path = |table: &toml::Value| table.get("path")?.as_str().map(PathBuf::from);
+
+    let mut targets = Vec::new();
+    if let Some(build) = manifest
+        .get("package")
+        .and_then(|package| package.get("build")?.as_str())
+    {
+        targets.push((PathBuf::from(build), CargoTarget::Build));
+    }
+    if let Some(lib) = manifest.get("lib").and_then(path) {
+        targets.push((lib, CargoTarget::Lib));
+    }
+    for (key, target) in [
+        ("bin", CargoTarget::Bin),
+        ("example", CargoTarget::Example),
+        ("test", CargoTarget::Test),
+        ("bench", CargoTarget::Bench),
+    ] {
+        let declared = manifest.get(key).and_then(toml::Value::as_array);
+        for file in declared.into_iter().flatten().filter_map(path) {
+            targets.push((file, target));
+        }
+    }
+    targets
+}
+
+impl FromStr for CargoTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CargoTarget::ALL
+            .into_iter()
+            .find(|target| target.to_string() == s)
+            .ok_or_else(|| format!("unknown target `{s}`"))
+    }
+}
+
+impl fmt::Display for CargoTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CargoTarget::Lib => write!(f, "lib"),
+            CargoTarget::Bin => write!(f, "bin"),
+            CargoTarget::Example => write!(f, "example"),
+            CargoTarget::Test => write!(f, "test"),
+            CargoTarget::Bench => write!(f, "bench"),
+            CargoTarget::Build => write!(f, "build"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::fixture;
+
+    const MANIFEST: &str = "[package]\nname = \"shapes\"\n";
+
+    #[test]
+    fn targets_follow_the_default_layout() {
+        let dir = fixture(&[("Cargo.toml", MANIFEST)]);
+        for (file, target) in [
+            ("examples/foo.rs", CargoTarget::Example),
+            ("examples/foo/main.rs", CargoTarget::Example),
+            ("src/bin/bar.rs", CargoTarget::Bin),
+            ("src/main.rs", CargoTarget::Bin),
+            ("src/lib.rs", CargoTarget::Lib),
+            ("src/shapes/circle.rs", CargoTarget::Lib),
+            ("tests/it.rs", CargoTarget::Test),
+            ("benches/speed.rs", CargoTarget::Bench),
+            ("build.rs", CargoTarget::Build),
+        ] {
+            assert_eq!(CargoTarget::of(&dir.join(file)), Some(target), "{file}");
+        }
+    }
+
+    #[test]
+    fn declared_paths_take_precedence() {
+        let manifest = format!(
+            "{MANIFEST}build = \"gen.rs\"\n\n[[bin]]\nname = \"cli\"\npath = \"src/cli.rs\"\n"
+        );
+        let dir = fixture(&[("Cargo.toml", &manifest)]);
+        assert_eq!(
+            CargoTarget::of(&dir.join("src/cli.rs")),
+            Some(CargoTarget::Bin)
+        );
+        assert_eq!(
+            CargoTarget::of(&dir.join("gen.rs")),
+            Some(CargoTarget::Build)
+        );
+        assert_eq!(
+            CargoTarget::of(&dir.join("src/lib.rs")),
+            Some(CargoTarget::Lib)
+        );
+    }
+
+    #[test]
+    fn targets_parse_from_their_names() {
+        for target in CargoTarget::ALL {
+            assert_eq!(target.to_string().parse(), Ok(target));
+        }
+        assert!("binary".parse::<CargoTarget>().is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::str::FromStr;
+
+use log::warn;
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// The colors the TUI is drawn with.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Text of the selected result, its own colors are kept if unset
+    pub selection_fg: Option<Color>,
+    pub selection_bg: Color,
+    pub border: Color,
+    /// Border of the pane that has focus
+    pub focused_border: Color,
+    /// The path above the preview
+    pub title: Color,
+    /// Background of query matches in the preview, they are shown reversed if unset
+    pub query_match: Option<Color>,
+    pub async_badge: Color,
+    pub unsafe_badge: Color,
+    /// Whether a function of an `impl` is a `[method]` or an `[assoc]`iated function
+    pub method_badge: Color,
+    pub impl_badge: Color,
+    pub cfg_badge: Color,
+    pub doc_badge: Color,
+    /// The Cargo target of results outside the library, e.g. `[example]`
+    pub target_badge: Color,
+    /// File paths and line counts next to results
+    pub path: Color,
+    /// Group headers
+    pub group: Color,
+    /// The check mark in front of marked results
+    pub mark: Color,
+    pub footer: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            selection_fg: None,
+            selection_bg: Color::LightCyan,
+            border: Color::Reset,
+            focused_border: Color::Yellow,
+            title: Color::LightCyan,
+            query_match: None,
+            async_badge: Color::Magenta,
+            unsafe_badge: Color::Red,
+            method_badge: Color::Blue,
+            impl_badge: Color::Cyan,
+            cfg_badge: Color::DarkGray,
+            doc_badge: Color::Magenta,
+            target_badge: Color::LightBlue,
+            path: Color::DarkGray,
+            group: Color::Yellow,
+            mark: Color::Green,
+            footer: Color::DarkGray,
+        }
+    }
+}
+
+impl Theme {
+    /// Names of the built in themes, for `theme.name` in the config.
+    pub const NAMES: [&str; 3] = ["default", "light", "mono"];
+
+    /// A built in theme by name.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default()),
+            // Light colors wash out on a light background
+            "light" => Some(Self {
+                selection_fg: Some(Color::White),
+                selection_bg: Color::Blue,
+                focused_border: Color::Blue,
+                title: Color::Blue,
+                method_badge: Color::Magenta,
+                impl_badge: Color::Blue,
+                target_badge: Color::Blue,
+                cfg_badge: Color::Gray,
+                path: Color::Gray,
+                group: Color::Rgb(0xaf, 0x5f, 0x00),
+                footer: Color::Gray,
+                ..Self::default()
+            }),
+            "mono" => Some(Self {
+                selection_fg: Some(Color::Black),
+                selection_bg: Color::White,
+                focused_border: Color::White,
+                title: Color::Reset,
+                async_badge: Color::Reset,
+                unsafe_badge: Color::Reset,
+                method_badge: Color::Gray,
+                impl_badge: Color::Gray,
+                cfg_badge: Color::DarkGray,
+                doc_badge: Color::Gray,
+                target_badge: Color::Gray,
+                path: Color::DarkGray,
+                group: Color::Reset,
+                mark: Color::Reset,
+                footer: Color::DarkGray,
+                ..Self::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// The theme the config asks for, a built in one with any colors it sets on top. Unknown
+    /// theme names and invalid colors are skipped with a warning.
+    pub fn from_config(config: &ThemeConfig) -> Self {
+        let mut theme = match config.name.as_deref() {
+            Some(name) => Self::named(name).unwrap_or_else(|| {
+                warn!(
+                    "Unknown theme {name:?}, expected one of {}",
+                    Self::NAMES.join(", ")
+                );
+                Self::default()
+            }),
+            None => Self::default(),
+        };
+        let colors = [
+            (
+                &config.selection_bg,
+                &mut theme.selection_bg,
+                "selection_bg",
+            ),
+            (&config.border, &mut theme.border, "border"),
+            (
+                &config.focused_border,
+                &mut theme.focused_border,
+                "focused_border",
+            ),
+            (&config.title, &mut theme.title, "title"),
+            (&config.async_badge, &mut theme.async_badge, "async_badge"),
+            (
+                &config.unsafe_badge,
+                &mut theme.unsafe_badge,
+                "unsafe_badge",
+            ),
+            (
+                &config.method_badge,
+                &mut theme.method_badge,
+                "method_badge",
+            ),
+            (&config.impl_badge, &mut theme.impl_badge, "impl_badge"),
+            (&config.cfg_badge, &mut theme.cfg_badge, "cfg_badge"),
+            (&config.doc_badge, &mut theme.doc_badge, "doc_badge"),
+            (
+                &config.target_badge,
+                &mut theme.target_badge,
+                "target_badge",
+            ),
+            (&config.path, &mut theme.path, "path"),
+            (&config.group, &mut theme.group, "group"),
+            (&config.mark, &mut theme.mark, "mark"),
+            (&config.footer, &mut theme.footer, "footer"),
+        ];
+        for (value, slot, key) in colors {
+            if let Some(color) = value.as_deref().and_then(|value| parse_color(key, value)) {
+                *slot = color;
+            }
+        }
+        let optional_colors = [
+            (
+                &config.selection_fg,
+                &mut theme.selection_fg,
+                "selection_fg",
+            ),
+            (&config.query_match, &mut theme.query_match, "query_match"),
+        ];
+        for (value, slot, key) in optional_colors {
+            if let Some(color) = value.as_deref().and_then(|value| parse_color(key, value)) {
+                *slot = Some(color);
+            }
+        }
+        theme
+    }
+}
+
+/// A color from the config, by name like `lightcyan`, as `#rrggbb` or as an ANSI index.
+fn parse_color(key: &str, value: &str) -> Option<Color> {
+    Color::from_str(value)
+        .inspect_err(|_| warn!("Ignoring theme.{key}: {value:?} is not a color"))
+        .ok()
+}
+
+/// The `[theme]` table of the config. Colors are given by name, e.g. `"lightcyan"`, as
+/// `"#rrggbb"` or as an ANSI index like `"208"`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Built in theme to start from, `"default"`, `"light"` or `"mono"`
+    pub name: Option<String>,
+    pub selection_fg: Option<String>,
+    pub selection_bg: Option<String>,
+    pub border: Option<String>,
+    pub focused_border: Option<String>,
+    pub title: Option<String>,
+    pub query_match: Option<String>,
+    pub async_badge: Option<String>,
+    pub unsafe_badge: Option<String>,
+    pub method_badge: Option<String>,
+    pub impl_badge: Option<String>,
+    pub cfg_badge: Option<String>,
+    pub doc_badge: Option<String>,
+    pub target_badge: Option<String>,
+    pub path: Option<String>,
+    pub group: Option<String>,
+    pub mark: Option<String>,
+    pub footer: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(toml: &str) -> Theme {
+        Theme::from_config(&toml::from_str(toml).unwrap())
+    }
+
+    #[test]
+    fn no_config_is_the_default_theme() {
+        let theme = theme("");
+        assert_eq!(theme.selection_bg, Color::LightCyan);
+        assert_eq!(theme.selection_fg, None);
+        assert_eq!(theme.query_match, None);
+    }
+
+    #[test]
+    fn colors_parse_by_name_hex_and_index() {
+        let theme = theme(
+            r##"
+            selection_bg = "blue"
+            border = "#ff8700"
+            footer = "208"
+            query_match = "lightyellow"
+            "##,
+        );
+        assert_eq!(theme.selection_bg, Color::Blue);
+        assert_eq!(theme.border, Color::Rgb(0xff, 0x87, 0x00));
+        assert_eq!(theme.footer, Color::Indexed(208));
+        assert_eq!(theme.query_match, Some(Color::LightYellow));
+    }
+
+    #[test]
+    fn colors_apply_on_top_of_a_named_theme() {
+        let theme = theme("name = \"light\"\ntitle = \"red\"");
+        assert_eq!(theme.title, Color::Red);
+        // The rest as the light theme has it
+        assert_eq!(theme.selection_bg, Color::Blue);
+        assert_eq!(theme.selection_fg, Some(Color::White));
+    }
+
+    #[test]
+    fn invalid_values_are_skipped() {
+        let theme = theme("name = \"solarized\"\nselection_bg = \"bluish\"\nmark = \"#12\"");
+        assert_eq!(theme.selection_bg, Color::LightCyan);
+        assert_eq!(theme.mark, Color::Green);
+        assert!(toml::from_str::<ThemeConfig>("selection = \"blue\"").is_err());
+    }
+
+    #[test]
+    fn every_named_theme_exists() {
+        for name in Theme::NAMES {
+            assert!(Theme::named(name).is_some(), "{name}");
+        }
+        assert!(Theme::named("solarized").is_none());
+    }
+}
@@ -1,38 +1,502 @@
-use std::path::Path;
+use std::{fs, path::Path};
 
 use ansi_to_tui::IntoText;
 use bat::{
-    PrettyPrinter,
+    PrettyPrinter, WrappingMode,
     line_range::{LineRange, LineRanges},
 };
+use log::debug;
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    layout::{Constraint, Direction, Layout, Margin, Position, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Wrap,
+    },
 };
 
-use crate::{app::App, error::Result};
+use tourust::{CargoTarget, Ref, RefKind, error::Result};
 
-fn highlight_syntax(file: &Path, line: usize) -> Result<String> {
+use crate::{
+    app::{App, Focus, Row, Sources},
+    query::Query,
+    theme::Theme,
+};
+
+/// Lines shown above the symbol in the preview when no context is configured
+const DEFAULT_CONTEXT: usize = 5;
+/// Lines shown below the end of the item when no context is configured
+const DEFAULT_TRAILING_CONTEXT: usize = 2;
+/// More context than any preview pane can show, so bigger values are clamped to this
+const MAX_CONTEXT: usize = 10_000;
+
+/// The lines to preview for the item from `line` to `end_line`, with `context` lines above and
+/// below it. The end may be past the end of the file.
+fn preview_range(line: usize, end_line: usize, context: Option<usize>) -> (usize, usize) {
+    let context = context.map(|context| context.min(MAX_CONTEXT));
+    let start = line
+        .saturating_sub(context.unwrap_or(DEFAULT_CONTEXT))
+        .max(1);
+    let end = end_line
+        .max(line)
+        .saturating_add(context.unwrap_or(DEFAULT_TRAILING_CONTEXT));
+    (start, end)
+}
+
+/// Highlight the item from `line` to `end_line` in `file`, with `context` lines above and below
+/// it. Bat stops at the end of the file on its own. With a `wrap` width bat breaks long lines
+/// itself, continuing them past an empty gutter so the line numbers stay with their lines.
+fn highlight_syntax(
+    file: &Path,
+    line: usize,
+    end_line: usize,
+    context: Option<usize>,
+    wrap: Option<usize>,
+) -> Result<String> {
+    let (start, end) = preview_range(line, end_line, context);
     let mut x = String::new();
-    PrettyPrinter::new()
+    let mut printer = PrettyPrinter::new();
+    match wrap {
+        Some(width) => printer
+            .term_width(width)
+            .wrapping_mode(WrappingMode::Character),
+        None => printer.wrapping_mode(WrappingMode::NoWrapping(false)),
+    };
+    printer
         .input_file(file)
+        // Whatever the extension, the file was indexed so it parses as Rust
+        .language("rust")
+        // Terminals expand tabs on their own, which throws off ratatui's layout
+        .tab_width(Some(TAB_WIDTH))
         .header(true)
         .line_numbers(true)
         .grid(true)
         .highlight(line)
-        .line_ranges(LineRanges::from(vec![LineRange::new(line, usize::MAX)]))
+        .line_ranges(LineRanges::from(vec![LineRange::new(start, end)]))
         .print_with_writer(Some(&mut x))?;
+    // Bat keeps the `\r` of CRLF line endings, which would send the cursor back to the start of
+    // the terminal line
+    x.retain(|ch| ch != '\r');
 
     Ok(x)
 }
 
+/// Columns between tab stops in the preview, as in bat's default.
+const TAB_WIDTH: usize = 4;
+
+/// `text` with its tabs expanded to the next tab stop.
+fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for ch in text.chars() {
+        if ch == '\t' {
+            let width = TAB_WIDTH - column % TAB_WIDTH;
+            expanded.extend(std::iter::repeat_n(' ', width));
+            column += width;
+        } else {
+            expanded.push(ch);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+/// Files larger than this are previewed as plain text, bat takes its time on huge generated
+/// files, especially ones on a single line.
+const MAX_HIGHLIGHT_SIZE: u64 = 1024 * 1024;
+/// Lines longer than this are cut off in the preview, there is no reading them anyway.
+const MAX_LINE_CHARS: usize = 2000;
+
+/// Width of the line number gutter of [`plain_source`], e.g. `  12 │ `
+const GUTTER_WIDTH: usize = 7;
+
+/// The same lines as [`highlight_syntax`] without any colors, laid out like bat's but skipping
+/// the cost of highlighting. Long lines are wrapped the same way too.
+fn plain_source(
+    file: &Path,
+    sources: &Sources,
+    line: usize,
+    end_line: usize,
+    context: Option<usize>,
+    wrap: Option<usize>,
+) -> Result<Text<'static>> {
+    let (start, end) = preview_range(line, end_line, context);
+    let src = sources.get(file)?;
+    let mut lines = vec![Line::styled(
+        format!("File: {}", file.display()),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+    for (i, text) in src
+        .lines()
+        .enumerate()
+        .skip(start - 1)
+        .take((end - start).saturating_add(1))
+    {
+        let number = i + 1;
+        let style = if number == line {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        // Cut before expanding, so a huge line costs no more than a short one
+        let cut = text.char_indices().nth(MAX_LINE_CHARS);
+        let mut text: Vec<char> = expand_tabs(cut.map_or(text, |(end, _)| &text[..end]))
+            .chars()
+            .collect();
+        if cut.is_some() {
+            text.push('…');
+        }
+        let width = wrap.map_or(text.len(), |width| width.saturating_sub(GUTTER_WIDTH));
+        let mut chunks = text.chunks(width.max(1));
+        let first: String = chunks.next().unwrap_or_default().iter().collect();
+        lines.push(Line::styled(format!("{number:4} │ {first}"), style));
+        for rest in chunks {
+            let rest: String = rest.iter().collect();
+            lines.push(Line::styled(format!("     │ {rest}"), style));
+        }
+    }
+    Ok(Text::from(lines))
+}
+
+/// The source around a ref, highlighted unless `highlight` is off or the file is huge and
+/// wrapped at the `wrap` width if given, or a note on why it can't be shown. The file may have
+/// been moved or deleted since it was indexed. Plain text is read through `sources`.
+fn preview(
+    r: &Ref,
+    sources: &Sources,
+    context: Option<usize>,
+    highlight: bool,
+    wrap: Option<usize>,
+) -> Text<'static> {
+    if !r.file.exists() {
+        return Text::styled(
+            format!("File not found: {}", r.file.display()),
+            Style::default().fg(Color::Red),
+        );
+    }
+    let large = fs::metadata(&r.file).is_ok_and(|meta| meta.len() > MAX_HIGHLIGHT_SIZE);
+    if highlight && large {
+        debug!(
+            "Previewing {} as plain text, it is too large",
+            r.file.display()
+        );
+    }
+    let text = if highlight && !large {
+        // Rather no colors than no preview
+        highlight_syntax(&r.file, r.line, r.end_line, context, wrap)
+            .and_then(|x| Ok(x.into_text()?))
+            .or_else(|err| {
+                debug!("Previewing {} as plain text: {}", r.file.display(), err);
+                plain_source(&r.file, sources, r.line, r.end_line, context, wrap)
+            })
+    } else {
+        plain_source(&r.file, sources, r.line, r.end_line, context, wrap)
+    };
+    match text {
+        Ok(text) => text,
+        Err(err) => Text::styled(
+            format!("Failed to preview {}: {}", r.file.display(), err),
+            Style::default().fg(Color::Red),
+        ),
+    }
+}
+
+/// The `impl` header a function in an `impl` block belongs to, laid out like a preview line, or
+/// `None` if the preview shows it anyway.
+fn impl_header(r: &Ref, sources: &Sources, context: Option<usize>) -> Option<String> {
+    let impl_line = r.impl_line?;
+    let (start, _) = preview_range(r.line, r.end_line, context);
+    if impl_line >= start {
+        return None;
+    }
+    let src = sources.get(&r.file).ok()?;
+    let line: String = src
+        .lines()
+        .nth(impl_line - 1)?
+        .trim_end()
+        .chars()
+        .take(MAX_LINE_CHARS)
+        .collect();
+    let text = expand_tabs(&line);
+    Some(format!("{impl_line:4} │ {text}"))
+}
+
+/// Widest a file path shown next to a result gets before it is cut off.
+const MAX_PATH_WIDTH: usize = 30;
+
+/// A path cut down to `max` chars from the left, so the file name survives, e.g.
+/// `…/app/tui.rs`. A file name longer than that is kept whole.
+fn truncate_path(path: &Path, max: usize) -> String {
+    let path = path.to_string_lossy();
+    let len = path.chars().count();
+    if len <= max {
+        return path.into_owned();
+    }
+    let file_name_len = path
+        .rsplit('/')
+        .next()
+        .map_or(len, |name| name.chars().count());
+    let keep = max.saturating_sub(1).max(file_name_len);
+    let tail: String = path.chars().skip(len.saturating_sub(keep)).collect();
+    format!("…{tail}")
+}
+
+/// The header of the section of results of `kind`.
+fn section_title(kind: RefKind) -> &'static str {
+    match kind {
+        RefKind::Fn => "Functions",
+        RefKind::Method => "Methods",
+        RefKind::Mod => "Modules",
+        RefKind::Enum => "Enums",
+        RefKind::Trait => "Traits",
+        RefKind::Struct => "Structs",
+        RefKind::Use => "Imports",
+        RefKind::Type => "Type aliases",
+        RefKind::Impl => "Impls",
+        RefKind::Const => "Constants",
+        RefKind::Macro => "Macros",
+        RefKind::Static => "Statics",
+        RefKind::Union => "Unions",
+        RefKind::Field => "Fields",
+        RefKind::Variant => "Variants",
+        RefKind::Derive => "Derives",
+    }
+}
+
+/// Narrowest a pane of the side by side comparison gets before the panes are stacked instead.
+const MIN_COMPARE_WIDTH: u16 = 50;
+
+/// The border of a pane, highlighted while it has focus.
+fn pane_block(app: &App, pane: Focus) -> Block<'static> {
+    let color = if app.focus == pane {
+        app.theme.focused_border
+    } else {
+        app.theme.border
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(color))
+}
+
+/// Render the highlighted source of `r` into `area`, returning how many lines it has.
+fn render_preview(frame: &mut Frame, app: &App, r: &Ref, area: Rect) -> u16 {
+    // Show where the symbol lives, e.g. `crate::app::App::run`
+    let mut preview_block = pane_block(app, Focus::Preview)
+        .style(Style::default())
+        .title(Line::styled(
+            format!(" {} ", r.path()),
+            Style::default()
+                .fg(app.theme.title)
+                .add_modifier(Modifier::BOLD),
+        ));
+    // Point out overloaded names, Alt-n hops between them
+    let others = app.same_name(r).len();
+    if others > 0 {
+        let definitions = if others == 1 {
+            "definition"
+        } else {
+            "definitions"
+        };
+        preview_block = preview_block.title_bottom(
+            Line::styled(
+                format!(" {others} other {definitions} named `{}` ", r.name),
+                Style::default().fg(app.theme.path),
+            )
+            .right_aligned(),
+        );
+    }
+    // Inside the borders
+    let wrap = app
+        .preview_wrap
+        .then(|| usize::from(area.width.saturating_sub(2)));
+    let mut text = preview(r, &app.sources, app.context, app.preview_highlight, wrap);
+    if app.highlight_query {
+        highlight_query(
+            &mut text,
+            Query::parse(&app.input).pattern,
+            app.theme.query_match,
+        );
+    }
+    let lines = u16::try_from(text.lines.len()).unwrap_or(u16::MAX);
+    let file_preview =
+        Paragraph::new(text).scroll((app.preview_scroll.min(lines.saturating_sub(1)), 0));
+    let mut inner = preview_block.inner(area);
+    frame.render_widget(preview_block, area);
+    // Pinned above the source, so the type a method is on stays in sight while scrolling
+    if let Some(header) = impl_header(r, &app.sources, app.context) {
+        let [header_area, source_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+        frame.render_widget(
+            Paragraph::new(Line::styled(header, Style::default().fg(app.theme.path))),
+            header_area,
+        );
+        inner = source_area;
+    }
+    frame.render_widget(file_preview, inner);
+    lines
+}
+
+/// Mark every occurrence of `query` in `text`, ignoring ASCII case, with the `color` background
+/// or by reversing the colors. Spans are split at the edges of a match so the syntax colors
+/// around it stay as they are.
+fn highlight_query(text: &mut Text<'static>, query: &str, color: Option<Color>) {
+    if query.is_empty() {
+        return;
+    }
+    let query = query.to_ascii_lowercase();
+    for line in &mut text.lines {
+        // Lowercasing ASCII keeps byte offsets, so they carry over to the original spans
+        let content: String = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        let matches: Vec<(usize, usize)> = content
+            .to_ascii_lowercase()
+            .match_indices(&query)
+            .map(|(start, m)| (start, start + m.len()))
+            .collect();
+        if matches.is_empty() {
+            continue;
+        }
+
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        for span in line.spans.drain(..) {
+            let (start, end) = (offset, offset + span.content.len());
+            offset = end;
+            let mut cuts: Vec<usize> = matches
+                .iter()
+                .flat_map(|&(m_start, m_end)| [m_start, m_end])
+                .filter(|&cut| cut > start && cut < end)
+                .collect();
+            cuts.push(end);
+
+            let mut from = start;
+            for to in cuts {
+                let matched = matches
+                    .iter()
+                    .any(|&(m_start, m_end)| (m_start..m_end).contains(&from));
+                let style = match (matched, color) {
+                    (true, Some(color)) => span.style.bg(color),
+                    (true, None) => span.style.add_modifier(Modifier::REVERSED),
+                    (false, _) => span.style,
+                };
+                spans.push(Span::styled(
+                    span.content[from - start..to - start].to_owned(),
+                    style,
+                ));
+                from = to;
+            }
+        }
+        line.spans = spans;
+    }
+}
+
+/// A result row, with the `async` and `unsafe` qualifiers of functions picked out as badges
+/// since they are easy to miss in the middle of a signature. Trait methods lead with the impl
+/// they come from. Further badges mark:
+/// - functions of an `impl` as methods or associated functions
+/// - items only compiled under some `#[cfg(..)]` with its predicate
+/// - items outside the library with their Cargo target
+fn result_line(r: &Ref, theme: &Theme) -> Line<'static> {
+    let mut line = sig_line(r, theme);
+    // Say which trait a method satisfies up front, e.g. `impl Display::fmt for Error`
+    if let Some(trait_sig) = r.trait_sig() {
+        line.spans.insert(
+            0,
+            Span::styled(
+                format!("{trait_sig}  "),
+                Style::default().fg(theme.impl_badge),
+            ),
+        );
+    }
+    // How it is called, `value.bar()` or `Type::new()`
+    let call = match r.kind {
+        RefKind::Method => Some("method"),
+        RefKind::Fn if r.owner.is_some() => Some("assoc"),
+        _ => None,
+    };
+    if let Some(call) = call {
+        line.spans.push(Span::styled(
+            format!(" [{call}]"),
+            Style::default().fg(theme.method_badge),
+        ));
+    }
+    if let Some(cfg) = &r.cfg {
+        line.spans.push(Span::styled(
+            format!(" [cfg: {cfg}]"),
+            Style::default().fg(theme.cfg_badge),
+        ));
+    }
+    // The library is what one usually browses, only point out the other targets
+    if let Some(target) = r.target.filter(|target| *target != CargoTarget::Lib) {
+        line.spans.push(Span::styled(
+            format!(" [{target}]"),
+            Style::default().fg(theme.target_badge),
+        ));
+    }
+    line
+}
+
+fn sig_line(r: &Ref, theme: &Theme) -> Line<'static> {
+    let sig = r.display_sig();
+    if !r.is_async && !r.is_unsafe {
+        return Line::raw(sig);
+    }
+    // Qualifiers all come before the `fn` keyword
+    let Some((qualifiers, rest)) = sig.split_once("fn ") else {
+        return Line::raw(sig);
+    };
+
+    let mut spans = Vec::new();
+    for word in qualifiers.split_inclusive(' ') {
+        let style = match word.trim_end() {
+            "async" => Style::default()
+                .fg(theme.async_badge)
+                .add_modifier(Modifier::BOLD),
+            "unsafe" => Style::default()
+                .fg(theme.unsafe_badge)
+                .add_modifier(Modifier::BOLD),
+            _ => Style::default(),
+        };
+        spans.push(Span::styled(word.to_owned(), style));
+    }
+    spans.push(Span::raw(format!("fn {rest}")));
+    Line::from(spans)
+}
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Smallest terminal the layout fits in: the results list plus a sliver of preview wide, and
+/// the search box, a few results and the footer high.
+const MIN_WIDTH: u16 = 50;
+const MIN_HEIGHT: u16 = 8;
+
 pub fn ui(frame: &mut Frame, app: &mut App) {
+    // Collapsed regions render garbage, ask for more room until the terminal is resized
+    let area = frame.area();
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        let message = Paragraph::new(format!(
+            "Terminal too small: {}x{}, need at least {}x{}",
+            area.width, area.height, MIN_WIDTH, MIN_HEIGHT
+        ))
+        .wrap(Wrap { trim: true })
+        .style(Style::default().fg(Color::Yellow));
+        frame.render_widget(message, area);
+        return;
+    }
+
     // Break up the frame into chunks
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
         .split(frame.area());
 
     // Our subchunks is the search results and code preview
@@ -42,40 +506,383 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
         .split(chunks[1]);
 
     // Create the top search block
-    let search_block = Block::default()
-        .borders(Borders::ALL)
-        .style(Style::default());
+    let search_block = pane_block(app, Focus::Input).style(Style::default());
     let search = Paragraph::new(app.input.clone()).block(search_block);
     frame.render_widget(search, chunks[0]);
+    // Place the terminal cursor inside the block's border, after the text before it
+    let cursor_offset = Line::raw(&app.input[..app.cursor]).width() as u16;
+    frame.set_cursor_position(Position::new(
+        chunks[0].x + 1 + cursor_offset,
+        chunks[0].y + 1,
+    ));
 
     // Create the search results
     let mut list_items: Vec<ListItem> = Vec::new();
-    for item in app.search_results.clone().into_sorted_iter() {
-        list_items.push(ListItem::from(item.0.sig.to_owned()));
+    let query = Query::parse(&app.input);
+    for row in app.rows() {
+        let line = match &row {
+            Row::Group {
+                name,
+                count,
+                expanded,
+            } => Line::styled(
+                format!("{} {name} ({count})", if *expanded { '▾' } else { '▸' }),
+                Style::default()
+                    .fg(app.theme.group)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Row::Section { kind, count } => Line::styled(
+                format!("{} ({count})", section_title(*kind)),
+                Style::default()
+                    .fg(app.theme.group)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            ),
+            Row::Ref(item) | Row::Member(item) => {
+                let mut line = result_line(item, &app.theme);
+                if app.matched_docs(&query, item) {
+                    line.spans.push(Span::styled(
+                        " [doc]",
+                        Style::default().fg(app.theme.doc_badge),
+                    ));
+                }
+                if app.show_line_counts {
+                    let count = item.line_count();
+                    line.spans.push(Span::styled(
+                        format!("  ({count} line{})", if count == 1 { "" } else { "s" }),
+                        Style::default().fg(app.theme.path),
+                    ));
+                }
+                if app.show_paths {
+                    let path = item.file.strip_prefix(app.root()).unwrap_or(&item.file);
+                    line.spans.push(Span::styled(
+                        format!(" {}", truncate_path(path, MAX_PATH_WIDTH)),
+                        Style::default().fg(app.theme.path),
+                    ));
+                }
+                if app.marked.contains(item) {
+                    line.spans.insert(
+                        0,
+                        Span::styled(
+                            "✓ ",
+                            Style::default()
+                                .fg(app.theme.mark)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                    );
+                }
+                // Indent the members of a group below its header
+                if matches!(row, Row::Member(_)) {
+                    line.spans.insert(0, Span::raw("  "));
+                }
+                line
+            }
+        };
+        list_items.push(ListItem::from(line));
     }
-    let search_results_block = Block::default()
-        .borders(Borders::ALL)
+    let result_count = list_items.len();
+    // Show where in the result list the selection is, e.g. ` 3/120 `
+    let position = match app.search_result_state.selected() {
+        Some(i) if result_count > 0 => {
+            format!(" {}/{} ", i.min(result_count - 1) + 1, result_count)
+        }
+        _ => format!(" {} ", result_count),
+    };
+    // Hint at the matches that were cut off, e.g. ` 3/500 of 1234 `
+    let position = if app.is_truncated() {
+        format!("{}of {} ", position, app.search_results.len())
+    } else {
+        position
+    };
+    let search_results_block = pane_block(app, Focus::Results)
+        .title_bottom(Line::from(position).right_aligned())
         .style(Style::default());
+    let mut selection_style = Style::default().bg(app.theme.selection_bg);
+    if let Some(fg) = app.theme.selection_fg {
+        selection_style = selection_style.fg(fg);
+    }
     let search_results_list = List::new(list_items)
         .block(search_results_block)
-        .highlight_style(Style::default().bg(Color::LightCyan));
+        .highlight_style(selection_style);
     frame.render_stateful_widget(
         search_results_list,
         subchunks[0],
         &mut app.search_result_state,
     );
+    let mut scrollbar_state = ScrollbarState::new(result_count)
+        .position(app.search_result_state.selected().unwrap_or_default());
+    frame.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight),
+        subchunks[0].inner(Margin {
+            vertical: 1,
+            horizontal: 0,
+        }),
+        &mut scrollbar_state,
+    );
 
-    // Create the code render
+    // Create the code render, comparing both refs side by side when exactly two are marked
     frame.render_widget(Clear, subchunks[1]);
-    let preview_block = Block::default()
-        .borders(Borders::ALL)
-        .style(Style::default());
-    if let Some(selected_ref) = app.get_selected_ref() {
-        let highlighted_text = highlight_syntax(&selected_ref.file, selected_ref.line)
-            .expect("Failed to highlight file")
-            .into_text()
-            .expect("Failed to translate from ANSI to TUI");
-        let file_preview = Paragraph::new(highlighted_text).block(preview_block);
-        frame.render_widget(file_preview, subchunks[1]);
+    let lines = if let [a, b] = app.marked.as_slice() {
+        // Stack them when side by side would leave too little of each line
+        let direction = if subchunks[1].width >= 2 * MIN_COMPARE_WIDTH {
+            Direction::Horizontal
+        } else {
+            Direction::Vertical
+        };
+        let panes = Layout::default()
+            .direction(direction)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+            .split(subchunks[1]);
+        render_preview(frame, app, a, panes[0]).max(render_preview(frame, app, b, panes[1]))
+    } else if let Some(selected_ref) = app.get_selected_ref() {
+        render_preview(frame, app, &selected_ref, subchunks[1])
+    } else {
+        0
+    };
+    // Don't let scrolling run on past the end, it would take as many presses to come back
+    app.preview_scroll = app.preview_scroll.min(lines.saturating_sub(1));
+
+    // Create the footer, showing a spinner while the index is still being built
+    let status = if app.indexer.is_some() {
+        format!(
+            "{} Indexing… {}/{} files, {} symbols",
+            SPINNER[app.tick / 4 % SPINNER.len()],
+            app.progress.done,
+            app.progress.total,
+            app.refs.len()
+        )
+    } else {
+        format!("{} symbols", app.refs.len())
+    };
+    let status = if app.is_busy() {
+        format!(
+            "{status}  {} jumping… Esc gives up",
+            SPINNER[app.tick / 4 % SPINNER.len()]
+        )
+    } else {
+        status
+    };
+    let status = if app.public_only {
+        format!("{status}  [pub only]")
+    } else {
+        status
+    };
+    let status = match &app.outline {
+        Some(file) => format!(
+            "{status}  [outline: {}]",
+            file.strip_prefix(app.root()).unwrap_or(file).display()
+        ),
+        None => status,
+    };
+    let status = if app.search_docs {
+        format!("{status}  [docs]")
+    } else {
+        status
+    };
+    let status = if app.grouped {
+        format!("{status}  [grouped]")
+    } else {
+        status
+    };
+    let status = if app.by_kind {
+        format!("{status}  [by kind]")
+    } else {
+        status
+    };
+    let status = if app.marked.is_empty() {
+        status
+    } else {
+        format!("{status}  [{} marked]", app.marked.len())
+    };
+    let status = match &app.status {
+        Some(message) => format!("{status}  {message}"),
+        None => status,
+    };
+    let status = format!("{status}  sort: {}  min score: {}", app.sort, app.min_score);
+    let footer = Paragraph::new(status).style(Style::default().fg(app.theme.footer));
+    frame.render_widget(footer, chunks[2]);
+
+    if app.show_help {
+        render_help(frame, area);
+    }
+}
+
+/// Keys and what they do, for the help popup.
+const KEYS: [(&str, &str); 34] = [
+    ("Enter", "select, or toggle a group"),
+    ("Ctrl/Alt-Enter", "select and keep browsing"),
+    ("Esc", "quit"),
+    ("Up/Down, Ctrl-j/k", "move, or scroll the preview"),
+    ("Tab/Shift-Tab", "mark and move"),
+    ("Left/Right", "collapse/expand a group"),
+    ("Ctrl-l/h", "focus the next/previous pane"),
+    ("Ctrl-w", "delete a word"),
+    ("Ctrl-u", "clear the query"),
+    ("Ctrl-v", "only public items"),
+    ("Ctrl-g", "group by name"),
+    ("Ctrl-s", "cycle the sort order"),
+    ("Ctrl-d", "search doc comments"),
+    ("Ctrl-f", "show file paths"),
+    ("Ctrl-n", "show line counts"),
+    ("Ctrl-t", "highlight the query in the preview"),
+    ("Ctrl-y", "copy the signature"),
+    ("Alt-y", "copy the whole item"),
+    ("Alt-l", "copy a markdown link"),
+    ("Alt-w", "wrap long lines in the preview"),
+    ("Alt-n", "next symbol with the same name"),
+    ("Alt-o", "outline the selected symbol's file"),
+    ("Alt-k", "split the results by kind"),
+    ("Ctrl-o", "open in the default app"),
+    ("Ctrl-q", "fill nvim's quickfix list"),
+    ("Ctrl-b", "copy the item to a scratch buffer"),
+    ("Ctrl-r", "reindex"),
+    ("Alt-Up/Down", "raise/lower the minimum score"),
+    ("F1", "toggle this help"),
+    ("'text", "exact match"),
+    ("@kind text", "only items of a kind, e.g. @fn"),
+    ("#pub text", "only public items"),
+    ("^text", "match at the start"),
+    ("text$", "match at the end"),
+];
+
+/// A popup over the middle of `area` listing the keys and the query syntax.
+fn render_help(frame: &mut Frame, area: Rect) {
+    let key_width = KEYS
+        .iter()
+        .map(|(key, _)| key.len())
+        .max()
+        .unwrap_or_default();
+    let lines: Vec<Line> = KEYS
+        .iter()
+        .map(|(key, action)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{key:key_width$}  "),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(*action),
+            ])
+        })
+        .collect();
+    let width = (key_width as u16 + 40).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let help = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Keys and query syntax "),
+    );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(help, popup);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::fixture::fixture;
+
+    fn text_of(text: &Text) -> String {
+        text.lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn missing_files_preview_as_a_note() {
+        let r = Ref {
+            file: PathBuf::from("/nonexistent/tourust/gone.rs"),
+            line: 1,
+            end_line: 1,
+            ..Default::default()
+        };
+        let text = preview(&r, &Sources::default(), None, true, None);
+        assert_eq!(
+            text_of(&text),
+            "File not found: /nonexistent/tourust/gone.rs"
+        );
+    }
+
+    #[test]
+    fn preview_spans_the_item_with_some_context() {
+        assert_eq!(
+            preview_range(10, 20, None),
+            (10 - DEFAULT_CONTEXT, 20 + DEFAULT_TRAILING_CONTEXT)
+        );
+        assert_eq!(preview_range(10, 20, Some(0)), (10, 20));
+        // Never before the first line
+        assert_eq!(preview_range(2, 3, Some(5)), (1, 8));
+    }
+
+    #[test]
+    fn huge_single_line_files_preview_cut_off() {
+        let src = "fn f() {} ".repeat(MAX_HIGHLIGHT_SIZE as usize / 10 + 1);
+        let dir = fixture(&[("generated.rs", &src)]);
+        let file = dir.join("generated.rs");
+        let r = Ref {
+            file: file.clone(),
+            line: 1,
+            end_line: 1,
+            ..Default::default()
+        };
+        // Too large to highlight, so plain text with the line cut off
+        let text = preview(&r, &Sources::default(), None, true, None);
+        assert_eq!(text.lines.len(), 2);
+        let line = text.lines[1].to_string();
+        let code = line.strip_prefix("   1 │ ").unwrap();
+        assert_eq!(code.chars().count(), MAX_LINE_CHARS + 1);
+        assert!(code.ends_with('…'));
+    }
+
+    #[test]
+    fn unknown_extensions_preview_as_rust() {
+        let dir = fixture(&[("parser.rsx", "// generated\nfn parse() -> u8 {\n    0\n}\n")]);
+        let file = dir.join("parser.rsx");
+        let r = Ref {
+            file: file.clone(),
+            line: 2,
+            end_line: 4,
+            ..Default::default()
+        };
+        // Bat can't tell from the extension, it is highlighted as Rust all the same
+        assert!(highlight_syntax(&file, 2, 4, Some(0), None).is_ok());
+        let text = text_of(&preview(&r, &Sources::default(), Some(0), true, None));
+        assert!(text.contains("fn parse() -> u8 {"), "{text}");
+        assert!(!text.contains("// generated"), "{text}");
+        assert!(!text.contains("Failed to preview"), "{text}");
+    }
+
+    #[test]
+    fn methods_far_below_their_impl_pin_its_header() {
+        let filler = "    // ...\n".repeat(20);
+        let src = format!("impl\tShape {{\n{filler}    fn area(&self) {{}}\n}}\n");
+        let dir = fixture(&[("lib.rs", &src)]);
+        let file = dir.join("lib.rs");
+        let method = Ref {
+            file: file.clone(),
+            line: 22,
+            end_line: 22,
+            impl_line: Some(1),
+            ..Default::default()
+        };
+        let sources = Sources::default();
+        assert_eq!(
+            impl_header(&method, &sources, Some(0)).as_deref(),
+            Some("   1 │ impl    Shape {")
+        );
+        // Not once the impl line is in the preview anyway
+        assert_eq!(impl_header(&method, &sources, Some(30)), None);
+        let free = Ref {
+            impl_line: None,
+            ..method
+        };
+        assert_eq!(impl_header(&free, &sources, Some(0)), None);
     }
 }